@@ -0,0 +1,338 @@
+use anyhow::{Result, anyhow};
+use chrono::Duration;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::LazyLock;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::config::{Settings, Uda, UdaType};
+use crate::datetime::{self, TaskDate};
+use crate::taskparser::{SIGNIFICANT_EMOJI, TAG_RE};
+
+// The values of the UDAs declared in the settings, by attribute name
+pub type Attributes = BTreeMap<String, AttributeValue>;
+
+// A Dataview inline field, e.g. [estimate:: 3h]
+static FIELD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(?<key>[\w-]+)::\s*(?<value>[^\]]*)\]").unwrap());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    String(String),
+    // Kept as text, normalised so 2.50 and 2.5 are the same number
+    Number(String),
+    Date(TaskDate),
+    Duration(Duration),
+}
+
+impl AttributeValue {
+    // Reads a value as it is written in markdown
+    pub fn parse(text: &str, kind: UdaType, time_format: &str) -> Result<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(anyhow!("Empty attribute value"));
+        }
+        match kind {
+            UdaType::String => Ok(AttributeValue::String(text.to_string())),
+            UdaType::Number => parse_number(text),
+            UdaType::Date => match TaskDate::parse_prefix(text, time_format)? {
+                (date, consumed) if text[consumed..].trim().is_empty() => {
+                    Ok(AttributeValue::Date(date))
+                }
+                _ => Err(anyhow!("Unexpected trailing characters in date: {}", text)),
+            },
+            UdaType::Duration => match datetime::parse_duration_prefix(text) {
+                Some((duration, consumed)) if text[consumed..].trim().is_empty() => {
+                    Ok(AttributeValue::Duration(duration))
+                }
+                _ => Err(anyhow!("Failed to parse duration: {}", text)),
+            },
+        }
+    }
+
+    // Reads a value as it is stored in taskchampion
+    pub fn from_tc(value: &str, kind: UdaType, tz: &chrono_tz::Tz) -> Result<Self> {
+        match kind {
+            UdaType::String => Ok(AttributeValue::String(value.to_string())),
+            UdaType::Number => parse_number(value),
+            UdaType::Date => value
+                .parse::<i64>()
+                .map(|_| AttributeValue::Date(TaskDate::from_timestamp(value, tz)))
+                .map_err(|_| anyhow!("Failed to parse timestamp: {}", value)),
+            UdaType::Duration => parse_iso_duration(value).map(AttributeValue::Duration),
+        }
+    }
+
+    pub fn format(&self, time_format: &str) -> String {
+        match self {
+            AttributeValue::String(value) | AttributeValue::Number(value) => value.clone(),
+            AttributeValue::Date(date) => date.format(time_format),
+            AttributeValue::Duration(duration) => datetime::format_precise_duration(*duration),
+        }
+    }
+
+    pub fn to_tc(&self, tz: &chrono_tz::Tz) -> String {
+        match self {
+            AttributeValue::String(value) | AttributeValue::Number(value) => value.clone(),
+            AttributeValue::Date(date) => date.to_timestamp(tz),
+            AttributeValue::Duration(duration) => format_iso_duration(*duration),
+        }
+    }
+}
+
+// Writes an attribute in the task line, after its emoji if it has one
+pub fn format_attribute(name: &str, value: &AttributeValue, settings: &Settings) -> String {
+    let value = value.format(&settings.markdown.time_format);
+    match settings.uda.get(name).and_then(|uda| uda.emoji.as_ref()) {
+        Some(emoji) => format!("{emoji} {value}"),
+        None => format!("[{name}:: {value}]"),
+    }
+}
+
+// Takes the declared attributes out of a piece of the task line. Fields that
+// don't hold a valid value are left where they are.
+pub fn take_attributes(text: &mut String, settings: &Settings) -> Attributes {
+    let mut attributes = Attributes::new();
+    if settings.uda.is_empty() {
+        return attributes;
+    }
+    let time_format = settings.markdown.time_format.as_str();
+
+    let mut fields: Vec<Range<usize>> = Vec::new();
+    for caps in FIELD_RE.captures_iter(text) {
+        let Some(uda) = settings.uda.get(&caps["key"]) else {
+            continue;
+        };
+        if let Ok(value) = AttributeValue::parse(&caps["value"], uda.kind, time_format) {
+            attributes.insert(caps["key"].to_string(), value);
+            fields.push(caps.get(0).unwrap().range());
+        }
+    }
+    remove_ranges(text, &fields);
+
+    // Emoji fields run up to the next field, the next tag or the end of the text
+    let dataview: Vec<usize> = FIELD_RE
+        .find_iter(text)
+        .map(|field| field.start())
+        .collect();
+    let tags: Vec<usize> = TAG_RE
+        .captures_iter(text)
+        .map(|caps| caps.name("tag").unwrap().start() - 1)
+        .collect();
+    let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+    let mut fields = Vec::new();
+    for (index, (start, grapheme)) in graphemes.iter().enumerate() {
+        let Some((name, uda)) = emoji_uda(grapheme, settings) else {
+            continue;
+        };
+        let end = graphemes[index + 1..]
+            .iter()
+            .find(|(position, next)| {
+                SIGNIFICANT_EMOJI.contains(next)
+                    || emoji_uda(next, settings).is_some()
+                    || dataview.contains(position)
+                    || tags.contains(position)
+            })
+            .map_or(text.len(), |(end, _)| *end);
+        let value = &text[start + grapheme.len()..end];
+        if let Ok(value) = AttributeValue::parse(value, uda.kind, time_format) {
+            attributes.insert(name.clone(), value);
+            fields.push(*start..end);
+        }
+    }
+    remove_ranges(text, &fields);
+    attributes
+}
+
+// The UDA written after the given emoji, ignoring any variation selector
+fn emoji_uda<'a>(grapheme: &str, settings: &'a Settings) -> Option<(&'a String, &'a Uda)> {
+    let grapheme = grapheme.trim_end_matches('\u{FE0F}');
+    settings.uda.iter().find(|(_, uda)| {
+        uda.emoji
+            .as_deref()
+            .is_some_and(|emoji| emoji.trim_end_matches('\u{FE0F}') == grapheme)
+    })
+}
+
+// Removes the ranges from the text, leaving a single space where they were
+fn remove_ranges(text: &mut String, ranges: &[Range<usize>]) {
+    for range in ranges.iter().rev() {
+        *text = format!(
+            "{} {}",
+            text[..range.start].trim_end(),
+            text[range.end..].trim_start()
+        )
+        .trim()
+        .to_string();
+    }
+}
+
+fn parse_number(text: &str) -> Result<AttributeValue> {
+    let number: f64 = text
+        .parse()
+        .map_err(|_| anyhow!("Failed to parse number: {}", text))?;
+    Ok(AttributeValue::Number(number.to_string()))
+}
+
+// Taskwarrior stores durations in ISO 8601, e.g. PT2H15M or P1DT2H. Plain
+// seconds are accepted too.
+fn parse_iso_duration(value: &str) -> Result<Duration> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        return Ok(Duration::seconds(seconds));
+    }
+    let error = || anyhow!("Failed to parse duration: {}", value);
+    let rest = value.strip_prefix('P').ok_or_else(error)?;
+    let mut duration = Duration::zero();
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' if number.is_empty() => in_time = true,
+            '0'..='9' => number.push(c),
+            _ => {
+                let amount: i64 = number.parse().map_err(|_| error())?;
+                number.clear();
+                duration += match (in_time, c) {
+                    (false, 'Y') => Duration::days(amount * 365),
+                    (false, 'M') => Duration::days(amount * 30),
+                    (false, 'W') => Duration::weeks(amount),
+                    (false, 'D') => Duration::days(amount),
+                    (true, 'H') => Duration::hours(amount),
+                    (true, 'M') => Duration::minutes(amount),
+                    (true, 'S') => Duration::seconds(amount),
+                    _ => return Err(error()),
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return Err(error());
+    }
+    Ok(duration)
+}
+
+fn format_iso_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    if seconds == 0 {
+        return String::from("PT0S");
+    }
+    let mut iso = String::from("PT");
+    for (amount, unit) in [
+        (seconds / 3600, 'H'),
+        (seconds / 60 % 60, 'M'),
+        (seconds % 60, 'S'),
+    ] {
+        if amount != 0 {
+            iso.push_str(&format!("{amount}{unit}"));
+        }
+    }
+    iso
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.uda.insert(
+            String::from("estimate"),
+            Uda {
+                kind: UdaType::Duration,
+                emoji: Some(String::from("⏲️")),
+            },
+        );
+        settings.uda.insert(
+            String::from("effort"),
+            Uda {
+                kind: UdaType::Number,
+                emoji: None,
+            },
+        );
+        settings.uda.insert(String::from("jira"), Uda::default());
+        settings.uda.insert(
+            String::from("ticket"),
+            Uda {
+                kind: UdaType::String,
+                emoji: Some(String::from("🎫")),
+            },
+        );
+        settings
+    }
+
+    #[test]
+    fn test_take_attributes() {
+        let settings = settings();
+        let mut text =
+            String::from("Write docs [jira:: DOC-12] ⏲️ 2h15m [effort:: 2.50] [other:: x]");
+        let attributes = take_attributes(&mut text, &settings);
+        assert_eq!(text, "Write docs [other:: x]");
+        assert_eq!(
+            attributes,
+            Attributes::from([
+                (
+                    String::from("effort"),
+                    AttributeValue::Number(String::from("2.5"))
+                ),
+                (
+                    String::from("estimate"),
+                    AttributeValue::Duration(Duration::minutes(135))
+                ),
+                (
+                    String::from("jira"),
+                    AttributeValue::String(String::from("DOC-12"))
+                ),
+            ])
+        );
+
+        // Emoji values stop at the next field and the variation selector is optional
+        let mut text = String::from("📅 2025-06-08 ⏲ 45m 🔼");
+        let attributes = take_attributes(&mut text, &settings);
+        assert_eq!(text, "📅 2025-06-08 🔼");
+        assert_eq!(
+            attributes["estimate"],
+            AttributeValue::Duration(Duration::minutes(45))
+        );
+
+        // String values stop at the next tag
+        let mut text = String::from("Fix login 🎫 DOC-12 #work #area/web");
+        let attributes = take_attributes(&mut text, &settings);
+        assert_eq!(text, "Fix login #work #area/web");
+        assert_eq!(
+            attributes["ticket"],
+            AttributeValue::String(String::from("DOC-12"))
+        );
+
+        // Invalid values stay in the text
+        let mut text = String::from("Test [effort:: lots] ⏲️ soon");
+        assert!(take_attributes(&mut text, &settings).is_empty());
+        assert_eq!(text, "Test [effort:: lots] ⏲️ soon");
+    }
+
+    #[test]
+    fn test_values() {
+        let tz = chrono_tz::UTC;
+        for (kind, markdown, stored) in [
+            (UdaType::String, "DOC-12", "DOC-12"),
+            (UdaType::Number, "3", "3"),
+            (UdaType::Date, "2025-06-10", "1749513600"),
+            (UdaType::Duration, "2h15m", "PT2H15M"),
+            (UdaType::Duration, "0m", "PT0S"),
+            (UdaType::Duration, "1m30s", "PT1M30S"),
+            (UdaType::Duration, "30s", "PT30S"),
+        ] {
+            let value = AttributeValue::parse(markdown, kind, "%H:%M").unwrap();
+            assert_eq!(value.to_tc(&tz), stored);
+            assert_eq!(AttributeValue::from_tc(stored, kind, &tz).unwrap(), value);
+            assert_eq!(value.format("%H:%M"), markdown);
+        }
+
+        assert_eq!(parse_iso_duration("P1DT2H").unwrap(), Duration::hours(26));
+        assert_eq!(parse_iso_duration("3600").unwrap(), Duration::hours(1));
+        assert!(parse_iso_duration("PT2").is_err());
+        assert!(parse_iso_duration("2h").is_err());
+    }
+}
@@ -1,18 +1,537 @@
 use anyhow::{Context, Result, anyhow};
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ignore::overrides::{Override, OverrideBuilder};
 use serde::Deserialize;
 use shellexpand::full;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
+use taskchampion::{ServerConfig, Uuid};
+
+use crate::taskparser::Priority;
 
 #[derive(Debug)]
 pub struct Config {
     pub vault_path: Option<PathBuf>,
     pub file_path: Option<PathBuf>,
     pub task_path: PathBuf,
+    pub backend: Backend,
     pub direction: Direction,
     pub tz: chrono_tz::Tz,
+    pub settings: Rc<Settings>,
+    pub sync: SyncServer,
+}
+
+// How the task database at task_path is stored
+#[derive(Deserialize, ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // The database of taskwarrior 3 and later
+    #[default]
+    Taskchampion,
+    // The pending.data and completed.data files of taskwarrior 2.x
+    Taskwarrior2,
+}
+
+// Where the task database is synced to around each run, like the sync.server
+// and sync.local settings of taskwarrior
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct SyncServer {
+    // A taskchampion sync server
+    pub url: Option<String>,
+    pub client_id: Option<String>,
+    pub encryption_secret: Option<String>,
+    // A directory holding the server's database instead
+    pub server_dir: Option<PathBuf>,
+}
+
+impl SyncServer {
+    // None when no sync target is configured
+    pub fn server_config(&self) -> Result<Option<ServerConfig>> {
+        match (&self.url, &self.server_dir) {
+            (Some(_), Some(_)) => Err(anyhow!(
+                "Only one of sync.url and sync.server_dir can be set"
+            )),
+            (Some(url), None) => {
+                let client_id = self
+                    .client_id
+                    .as_ref()
+                    .context("sync.client_id is required with sync.url")?;
+                let client_id = Uuid::parse_str(client_id)
+                    .with_context(|| format!("Invalid sync.client_id: {client_id}"))?;
+                let encryption_secret = self
+                    .encryption_secret
+                    .as_ref()
+                    .context("sync.encryption_secret is required with sync.url")?;
+                Ok(Some(ServerConfig::Remote {
+                    url: url.clone(),
+                    client_id,
+                    encryption_secret: encryption_secret.as_bytes().to_vec(),
+                }))
+            }
+            (None, Some(server_dir)) => Ok(Some(ServerConfig::Local {
+                server_dir: server_dir.clone(),
+            })),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+// Options that control how tasks are read from and written to markdown
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Settings {
+    pub markdown: Markdown,
+    pub mapping: Mapping,
+    pub project: ProjectInference,
+    pub filter: Filter,
+    // Taskwarrior UDAs synced with the notes, by attribute name
+    pub uda: BTreeMap<String, Uda>,
+}
+
+// A user defined attribute written in the task line, either as a Dataview
+// field like [estimate:: 3h] or after an emoji of its own
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Uda {
+    #[serde(rename = "type")]
+    pub kind: UdaType,
+    pub emoji: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UdaType {
+    #[default]
+    String,
+    Number,
+    Date,
+    // Written like 2h15m, stored like taskwarrior as PT2H15M
+    Duration,
+}
+
+// Rules deciding which notes and task lines are synced
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Filter {
+    // Globs relative to the vault. When set, only matching notes are synced
+    pub include: Vec<String>,
+    // Globs relative to the vault for notes that are never synced
+    pub exclude: Vec<String>,
+    // Tag every synced task must have, e.g. task for #task
+    pub tag: Option<String>,
+    // Fields every synced task must have
+    pub require: Vec<RequiredField>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RequiredField {
+    Due,
+    Scheduled,
+    Start,
+    // Any of due, scheduled or start
+    Date,
+    Priority,
+    Project,
+}
+
+impl Filter {
+    // Builds a matcher for the include and exclude globs rooted at the vault
+    pub fn overrides<P: AsRef<Path>>(&self, vault_path: P) -> Result<Override> {
+        let mut builder = OverrideBuilder::new(vault_path);
+        for glob in &self.include {
+            builder
+                .add(glob)
+                .with_context(|| format!("Invalid include glob: {glob}"))?;
+        }
+        for glob in &self.exclude {
+            builder
+                .add(&format!("!{glob}"))
+                .with_context(|| format!("Invalid exclude glob: {glob}"))?;
+        }
+        builder.build().context("Failed to build folder filter")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Markdown {
+    // chrono format string used for the optional time after a date
+    pub time_format: String,
+    // Where tags added in taskwarrior are placed in the task line
+    pub tag_placement: TagPlacement,
+    // Text marking a checkbox as a task, like the Tasks plugin global filter.
+    // Empty when every checkbox is a task.
+    pub global_filter: String,
+    // Skip checkboxes in the frontmatter at the start of a note
+    pub skip_frontmatter: bool,
+    // Sync tasks inside blockquotes and callouts
+    pub quoted_tasks: bool,
+    // How the taskwarrior uuid is hidden in the task line
+    pub uuid_format: UuidFormat,
+    // Write a short prefix of the uuid instead of the whole thing
+    pub short_ids: bool,
+    // Minimum length of a short id, longer ids are used to avoid collisions
+    pub short_id_length: usize,
+    // Add a ^block-id to synced tasks so links can open the task line itself
+    pub block_ids: bool,
+    // The kind of obsidian uri added to tasks as an annotation
+    pub link_format: LinkFormat,
+    // How a task started in taskwarrior is shown
+    pub active_format: ActiveFormat,
+    // Checkbox character marking an active task, e.g. - [/]
+    pub active_status: char,
+    // Show the time tracked in taskwarrior on each task, e.g. ⏱ 2h15m
+    pub time_tracking: bool,
+    // How the taskwarrior until date is written
+    pub until_format: UntilFormat,
+    // What happens to pending tasks past their until date
+    pub expired: Expired,
+    // Tag added to expired tasks with expired = "tag"
+    pub expired_tag: String,
+}
+
+impl Default for Markdown {
+    fn default() -> Self {
+        Markdown {
+            time_format: String::from("%H:%M"),
+            tag_placement: TagPlacement::End,
+            global_filter: String::new(),
+            skip_frontmatter: true,
+            quoted_tasks: false,
+            uuid_format: UuidFormat::Link,
+            short_ids: false,
+            short_id_length: 8,
+            block_ids: false,
+            link_format: LinkFormat::Open,
+            active_format: ActiveFormat::Status,
+            active_status: '/',
+            time_tracking: false,
+            until_format: UntilFormat::Emoji,
+            expired: Expired::Cancel,
+            expired_tag: String::from("expired"),
+        }
+    }
+}
+
+// All of these are read, only the configured one is written
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UuidFormat {
+    // [[uuid: ...|⚔️]], a link showing only the ⚔️
+    Link,
+    // %%tw:...%%, an obsidian comment
+    Comment,
+    // <!-- tw:... -->, an HTML comment
+    Html,
+    // 🆔 ..., an id in the style of the Tasks plugin
+    Id,
+}
+
+// Both are read, only the configured one is written
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UntilFormat {
+    // ⌛ 2025-06-10
+    Emoji,
+    // [until:: 2025-06-10], a Dataview inline field
+    Dataview,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Expired {
+    // Show the task as canceled, which deletes it like taskwarrior would
+    Cancel,
+    // Add markdown.expired_tag to the task
+    Tag,
+    // Leave the task as it is
+    Keep,
+}
+
+// Both are read, only the configured one is written
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActiveFormat {
+    // The active status character in the checkbox, e.g. - [/]
+    Status,
+    // ▶️ followed by the time the task was started
+    Marker,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkFormat {
+    // obsidian://open, built into obsidian
+    Open,
+    // obsidian://advanced-uri, from the Advanced URI plugin
+    AdvancedUri,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagPlacement {
+    // After the description, e.g. Buy milk #errand
+    End,
+    // In a group after the description, e.g. Buy milk (#errand)
+    Parentheses,
+}
+
+// Where a task's project comes from when it doesn't have an explicit 🔨
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProjectInference {
+    // Tried in order, the first source with a project wins
+    pub sources: Vec<ProjectSource>,
+    // Joins the folder levels, e.g. Work/ClientA becomes Work.ClientA
+    pub separator: String,
+    // Frontmatter key holding the project
+    pub key: String,
+}
+
+impl Default for ProjectInference {
+    fn default() -> Self {
+        ProjectInference {
+            sources: Vec::new(),
+            separator: String::from("."),
+            key: String::from("project"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectSource {
+    // The folders between the vault and the note
+    Folder,
+    // A key in the note's frontmatter
+    Frontmatter,
+    // The name of the note
+    Note,
+}
+
+// The taskchampion attribute each obsidian field is read from and written to.
+// Attributes that taskwarrior doesn't know about are stored as UDAs.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Mapping {
+    pub due: String,
+    pub scheduled: String,
+    pub start: String,
+    pub created: String,
+    pub done: String,
+    pub canceled: String,
+    pub until: String,
+    pub project: String,
+    // UDA holding the note the task is in, relative to the vault. Empty to disable
+    pub source_file: String,
+    pub priority: PriorityMapping,
+    pub tags: TagMapping,
+}
+
+impl Mapping {
+    // The active state is kept in the taskwarrior start attribute, unless 🛫
+    // is already stored there
+    pub fn tracks_active(&self) -> bool {
+        self.start != "start"
+    }
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            due: String::from("due"),
+            scheduled: String::from("scheduled"),
+            start: String::from("wait"),
+            created: String::from("created"),
+            done: String::from("end"),
+            canceled: String::from("end"),
+            until: String::from("until"),
+            project: String::from("project"),
+            source_file: String::from("sourcefile"),
+            priority: PriorityMapping::default(),
+            tags: TagMapping::default(),
+        }
+    }
+}
+
+// How nested obsidian tags such as #area/work are stored, since taskwarrior
+// doesn't allow '/' in tags
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedTags {
+    // Each level becomes its own tag, the hierarchy is lost
+    Split,
+    // The '/' is replaced with the separator, e.g. area.work
+    Separator,
+    // Each level becomes its own tag and the full paths are kept in an attribute
+    Attribute,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TagMapping {
+    pub nested: NestedTags,
+    pub separator: String,
+    pub attribute: String,
+}
+
+impl Default for TagMapping {
+    fn default() -> Self {
+        TagMapping {
+            nested: NestedTags::Separator,
+            separator: String::from("."),
+            attribute: String::from("obsidian_tags"),
+        }
+    }
+}
+
+impl TagMapping {
+    // Converts obsidian tags into the taskchampion tags that represent them
+    pub fn encode<S: AsRef<str>>(&self, tags: &[S]) -> Vec<String> {
+        let mut encoded: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = tag.as_ref();
+            let parts = match self.nested {
+                NestedTags::Separator => vec![tag.replace('/', &self.separator)],
+                NestedTags::Split | NestedTags::Attribute => {
+                    tag.split('/').map(|part| part.to_string()).collect()
+                }
+            };
+            for part in parts {
+                if !part.is_empty() && !encoded.contains(&part) {
+                    encoded.push(part);
+                }
+            }
+        }
+        encoded
+    }
+
+    // Value of the attribute holding the full tag paths, if one is used
+    pub fn stored<S: AsRef<str>>(&self, tags: &[S]) -> Option<String> {
+        if self.nested != NestedTags::Attribute || tags.is_empty() {
+            return None;
+        }
+        let paths: Vec<&str> = tags.iter().map(|tag| tag.as_ref()).collect();
+        Some(paths.join(" "))
+    }
+
+    // Converts taskchampion tags back into obsidian tags. With the attribute
+    // encoding, a stored path is only restored while all of its levels are still
+    // tagged in taskchampion.
+    pub fn decode(&self, tc_tags: &[String], stored: Option<&str>) -> Vec<String> {
+        match self.nested {
+            NestedTags::Split => tc_tags.to_vec(),
+            NestedTags::Separator => tc_tags
+                .iter()
+                .map(|tag| tag.replace(&self.separator, "/"))
+                .collect(),
+            NestedTags::Attribute => {
+                let mut tags = Vec::new();
+                let mut covered = Vec::new();
+                for path in stored.unwrap_or("").split_whitespace() {
+                    let parts = self.encode(&[path]);
+                    if !parts.is_empty() && parts.iter().all(|part| tc_tags.contains(part)) {
+                        tags.push(path.to_string());
+                        covered.extend(parts);
+                    }
+                }
+                for tag in tc_tags {
+                    if !covered.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+                tags
+            }
+        }
+    }
+}
+
+// The attribute and value each obsidian priority is stored as. An empty value
+// leaves the attribute unset.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PriorityMapping {
+    pub attribute: String,
+    pub lowest: String,
+    pub low: String,
+    pub normal: String,
+    pub medium: String,
+    pub high: String,
+    pub highest: String,
+    // Tag added to tasks with the highest priority, empty to disable
+    pub highest_tag: String,
+    // UDA that keeps the exact obsidian priority when several priorities share a
+    // value, empty to disable
+    pub exact_attribute: String,
+}
+
+impl Default for PriorityMapping {
+    fn default() -> Self {
+        PriorityMapping {
+            attribute: String::from("priority"),
+            lowest: String::from("L"),
+            low: String::from("L"),
+            normal: String::new(),
+            medium: String::from("M"),
+            high: String::from("H"),
+            highest: String::from("H"),
+            highest_tag: String::from("next"),
+            exact_attribute: String::from("obsidian_priority"),
+        }
+    }
+}
+
+impl PriorityMapping {
+    pub fn value(&self, priority: &Priority) -> &str {
+        match priority {
+            Priority::Lowest => &self.lowest,
+            Priority::Low => &self.low,
+            Priority::Normal => &self.normal,
+            Priority::Medium => &self.medium,
+            Priority::High => &self.high,
+            Priority::Highest => &self.highest,
+        }
+    }
+
+    // Finds the priority stored in taskchampion. The exact priority is used as long
+    // as it still agrees with the value and tag, since either may have been changed
+    // in taskwarrior without updating it.
+    pub fn priority(&self, value: &str, has_highest_tag: bool, exact: Option<&str>) -> Priority {
+        if let Some(exact) = exact.and_then(|name| name.parse::<Priority>().ok()) {
+            let tag_matches =
+                exact != Priority::Highest || self.highest_tag.is_empty() || has_highest_tag;
+            if self.value(&exact) == value && tag_matches {
+                return exact;
+            }
+        }
+        self.closest_priority(value, has_highest_tag)
+    }
+
+    // When several priorities share the same value, the one closest to normal wins
+    // unless the highest tag is present
+    fn closest_priority(&self, value: &str, has_highest_tag: bool) -> Priority {
+        if value == self.highest && !self.highest_tag.is_empty() && has_highest_tag {
+            return Priority::Highest;
+        }
+        let candidates = [
+            Priority::Normal,
+            Priority::Medium,
+            Priority::Low,
+            Priority::High,
+            Priority::Lowest,
+            Priority::Highest,
+        ];
+        candidates
+            .into_iter()
+            .filter(|pri| *pri != Priority::Highest || self.highest_tag.is_empty())
+            .find(|pri| self.value(pri) == value)
+            .unwrap_or_default()
+    }
 }
 
 const DEFAULT_PATH: &str = "~/.sharptask/config.toml";
@@ -23,8 +542,14 @@ struct ConfigFile {
     vault_path: Option<PathBuf>,
     #[serde(default = "default_task_path")]
     task_path: Option<PathBuf>,
+    #[serde(default)]
+    backend: Backend,
     #[serde(default = "default_timezone")]
     timezone: Option<String>,
+    #[serde(default)]
+    sync: SyncServer,
+    #[serde(flatten)]
+    settings: Settings,
 }
 
 fn default_task_path() -> Option<PathBuf> {
@@ -41,7 +566,10 @@ impl Default for ConfigFile {
         ConfigFile {
             vault_path: None,
             task_path: default_task_path(),
+            backend: Backend::default(),
             timezone: default_timezone(),
+            sync: SyncServer::default(),
+            settings: Settings::default(),
         }
     }
 }
@@ -53,6 +581,8 @@ struct Cli {
     target: Target,
     #[arg(short, long)]
     task_db: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    backend: Option<Backend>,
     #[arg(short, long)]
     config: Option<PathBuf>,
     #[arg(long = "tz")]
@@ -65,6 +595,42 @@ struct Cli {
 pub enum Direction {
     MdToTc,
     TcToMd,
+    // Rewrites the uuid markers in the vault in another format
+    Migrate {
+        // Defaults to markdown.uuid_format
+        #[arg(long)]
+        to: Option<UuidFormat>,
+    },
+    // Removes the uuid markers so the tasks are no longer synced
+    Unlink {
+        // What happens to the taskwarrior tasks that were linked
+        #[arg(long, value_enum, default_value_t = UnlinkAction::Keep)]
+        tasks: UnlinkAction,
+        // Report what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    // Summarises the synced tasks of the vault
+    Report {
+        #[command(subcommand)]
+        report: Report,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    // Time tracked in taskwarrior per note, project and tag
+    Time,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlinkAction {
+    // Leave the tasks as they are
+    Keep,
+    // Complete any pending tasks
+    Complete,
+    // Delete the tasks
+    Delete,
 }
 
 #[derive(Args, Debug)]
@@ -119,9 +685,18 @@ pub fn get() -> Config {
     Config {
         vault_path,
         task_path,
+        backend: cli.backend.unwrap_or(parsed_config.backend),
         file_path: cli.target.file,
         direction: cli.dir,
         tz,
+        settings: Rc::new(parsed_config.settings),
+        sync: SyncServer {
+            server_dir: parsed_config.sync.server_dir.map(|path| {
+                let path_str = path.to_string_lossy();
+                PathBuf::from(shellexpand::tilde(&path_str).into_owned())
+            }),
+            ..parsed_config.sync
+        },
     }
 }
 
@@ -143,6 +718,8 @@ fn parse<P: AsRef<Path>>(config_path: P) -> Result<ConfigFile> {
 mod tests {
     use super::*;
 
+    use pretty_assertions::assert_eq;
+
     #[test]
     fn parse_simple_config() {
         let test_config = r#"vault_path = "~/myVault"
@@ -152,5 +729,233 @@ mod tests {
         let my_config = parse(test_file).unwrap();
         assert_eq!(my_config.vault_path.unwrap(), PathBuf::from("~/myVault"));
         assert_eq!(my_config.task_path.unwrap(), PathBuf::from("~/taskPath"));
+        assert_eq!(my_config.backend, Backend::Taskchampion);
+        assert_eq!(my_config.settings, Settings::default());
+
+        let test_config = r#"backend = "taskwarrior2""#;
+        let test_file = testfile::from(test_config);
+        assert_eq!(parse(test_file).unwrap().backend, Backend::Taskwarrior2);
+    }
+
+    #[test]
+    fn parse_markdown_config() {
+        let test_config = r#"vault_path = "~/myVault"
+
+                             [markdown]
+                             time_format = "%I:%M %p"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let my_config = parse(test_file).unwrap();
+        assert_eq!(my_config.settings.markdown.time_format, "%I:%M %p");
+        assert_eq!(my_config.settings.markdown.uuid_format, UuidFormat::Link);
+
+        let test_config = r#"[markdown]
+                             uuid_format = "comment"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let my_config = parse(test_file).unwrap();
+        assert_eq!(my_config.settings.markdown.uuid_format, UuidFormat::Comment);
+
+        let test_config = r#"[markdown]
+                             active_format = "marker"
+                             active_status = "s"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let markdown = parse(test_file).unwrap().settings.markdown;
+        assert_eq!(markdown.active_format, ActiveFormat::Marker);
+        assert_eq!(markdown.active_status, 's');
+    }
+
+    #[test]
+    fn parse_mapping_config() {
+        let test_config = r#"[mapping]
+                             start = "start"
+
+                             [mapping.priority]
+                             attribute = "importance"
+                             highest = "critical"
+                             highest_tag = ""
+                         "#;
+        let test_file = testfile::from(test_config);
+        let mapping = parse(test_file).unwrap().settings.mapping;
+        assert_eq!(mapping.start, "start");
+        assert_eq!(mapping.due, "due");
+        assert_eq!(mapping.priority.attribute, "importance");
+        assert_eq!(mapping.priority.value(&Priority::High), "H");
+        assert_eq!(
+            mapping.priority.priority("critical", false, None),
+            Priority::Highest
+        );
+    }
+
+    #[test]
+    fn parse_project_config() {
+        let test_config = r#"[project]
+                             sources = ["frontmatter", "folder"]
+                             separator = "/"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let project = parse(test_file).unwrap().settings.project;
+        assert_eq!(
+            project.sources,
+            [ProjectSource::Frontmatter, ProjectSource::Folder]
+        );
+        assert_eq!(project.separator, "/");
+        assert_eq!(project.key, "project");
+    }
+
+    #[test]
+    fn parse_filter_config() {
+        let test_config = r#"[filter]
+                             include = ["Projects/**"]
+                             exclude = ["Projects/Archive/**", "Templates/**"]
+                             tag = "task"
+                             require = ["due"]
+                         "#;
+        let test_file = testfile::from(test_config);
+        let filter = parse(test_file).unwrap().settings.filter;
+        assert_eq!(filter.tag.as_deref(), Some("task"));
+        assert_eq!(filter.require, [RequiredField::Due]);
+
+        let overrides = filter.overrides("/vault").unwrap();
+        let ignored = |path: &str| overrides.matched(path, false).is_ignore();
+        assert!(!ignored("/vault/Projects/Acme.md"));
+        assert!(!ignored("/vault/Projects/Acme/Meeting.md"));
+        assert!(ignored("/vault/Projects/Archive/Old.md"));
+        assert!(ignored("/vault/Templates/Daily.md"));
+        assert!(ignored("/vault/Inbox.md"));
+        assert!(!overrides.matched("/vault/Projects", true).is_ignore());
+
+        // Without include globs everything that isn't excluded is synced
+        let filter = Filter {
+            exclude: vec![String::from("Templates/**")],
+            ..Filter::default()
+        };
+        let overrides = filter.overrides("/vault").unwrap();
+        assert!(!overrides.matched("/vault/Inbox.md", false).is_ignore());
+        assert!(
+            overrides
+                .matched("/vault/Templates/Daily.md", false)
+                .is_ignore()
+        );
+    }
+
+    #[test]
+    fn parse_sync_config() {
+        let test_config = r#"[sync]
+                             server_dir = "/tmp/sync"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let sync = parse(test_file).unwrap().sync;
+        assert!(matches!(
+            sync.server_config().unwrap(),
+            Some(ServerConfig::Local { server_dir }) if server_dir == Path::new("/tmp/sync")
+        ));
+
+        let test_config = r#"[sync]
+                             url = "https://tasks.example.com"
+                             client_id = "0b5bb8c0-3c1e-4c4e-9d5e-6d2a2f1ac4f1"
+                             encryption_secret = "hunter2"
+                         "#;
+        let test_file = testfile::from(test_config);
+        let sync = parse(test_file).unwrap().sync;
+        assert!(matches!(
+            sync.server_config().unwrap(),
+            Some(ServerConfig::Remote { url, .. }) if url == "https://tasks.example.com"
+        ));
+
+        let missing_secret = SyncServer {
+            encryption_secret: None,
+            ..sync.clone()
+        };
+        assert!(missing_secret.server_config().is_err());
+        let both = SyncServer {
+            server_dir: Some(PathBuf::from("/tmp/sync")),
+            ..sync
+        };
+        assert!(both.server_config().is_err());
+        assert!(SyncServer::default().server_config().unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_uda_config() {
+        let test_config = r#"[uda.estimate]
+                             type = "duration"
+                             emoji = "⏲️"
+
+                             [uda.jira]
+                         "#;
+        let test_file = testfile::from(test_config);
+        let udas = parse(test_file).unwrap().settings.uda;
+        assert_eq!(udas["estimate"].kind, UdaType::Duration);
+        assert_eq!(udas["estimate"].emoji.as_deref(), Some("⏲️"));
+        assert_eq!(udas["jira"], Uda::default());
+    }
+
+    #[test]
+    fn tag_mapping() {
+        let tags = ["area/work/client", "simple"];
+        let tc_tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        let mapping = TagMapping::default();
+        assert_eq!(mapping.encode(&tags), ["area.work.client", "simple"]);
+        assert_eq!(mapping.stored(&tags), None);
+        assert_eq!(mapping.decode(&mapping.encode(&tags), None), tags);
+
+        let mapping = TagMapping {
+            nested: NestedTags::Split,
+            ..TagMapping::default()
+        };
+        assert_eq!(mapping.encode(&tags), ["area", "work", "client", "simple"]);
+        assert_eq!(
+            mapping.decode(&mapping.encode(&tags), None),
+            ["area", "work", "client", "simple"]
+        );
+
+        let mapping = TagMapping {
+            nested: NestedTags::Attribute,
+            ..TagMapping::default()
+        };
+        let stored = mapping.stored(&tags);
+        assert_eq!(stored.as_deref(), Some("area/work/client simple"));
+        assert_eq!(
+            mapping.decode(&mapping.encode(&tags), stored.as_deref()),
+            tags
+        );
+
+        // A level removed in taskwarrior breaks the stored path apart
+        assert_eq!(
+            mapping.decode(
+                &tc_tags(&["area", "client", "simple", "new"]),
+                stored.as_deref()
+            ),
+            ["simple", "area", "client", "new"]
+        );
+    }
+
+    #[test]
+    fn priority_mapping() {
+        let mapping = PriorityMapping::default();
+        assert_eq!(mapping.priority("", false, None), Priority::Normal);
+        assert_eq!(mapping.priority("L", false, None), Priority::Low);
+        assert_eq!(mapping.priority("M", false, None), Priority::Medium);
+        assert_eq!(mapping.priority("H", false, None), Priority::High);
+        assert_eq!(mapping.priority("H", true, None), Priority::Highest);
+        assert_eq!(mapping.priority("X", false, None), Priority::Normal);
+
+        // The exact priority is only trusted while it agrees with the value
+        assert_eq!(
+            mapping.priority("L", false, Some("lowest")),
+            Priority::Lowest
+        );
+        assert_eq!(mapping.priority("H", false, Some("lowest")), Priority::High);
+        assert_eq!(
+            mapping.priority("H", false, Some("highest")),
+            Priority::High
+        );
+        assert_eq!(
+            mapping.priority("H", true, Some("highest")),
+            Priority::Highest
+        );
     }
 }
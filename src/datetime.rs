@@ -0,0 +1,279 @@
+use anyhow::{Result, anyhow};
+use chrono::{
+    DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+pub const MIDNIGHT: NaiveTime = NaiveTime::from_hms_opt(0, 0, 0).expect("Invalid time");
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+// A date as written in obsidian, optionally with a time of day. A date without
+// a time refers to midnight of that day.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TaskDate {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+}
+
+impl TaskDate {
+    pub fn new(date: NaiveDate, time: Option<NaiveTime>) -> Self {
+        TaskDate { date, time }
+    }
+
+    pub fn naive(&self) -> NaiveDateTime {
+        self.date.and_time(self.time.unwrap_or(MIDNIGHT))
+    }
+
+    pub fn format(&self, time_format: &str) -> String {
+        match self.time {
+            Some(time) => format!(
+                "{} {}",
+                self.date.format(DATE_FORMAT),
+                time.format(time_format)
+            ),
+            None => self.date.format(DATE_FORMAT).to_string(),
+        }
+    }
+
+    // Parses a date followed by an optional time from the beginning of the input.
+    // Returns the date along with the number of bytes consumed from the input.
+    pub fn parse_prefix(input: &str, time_format: &str) -> Result<(TaskDate, usize)> {
+        let trimmed = input.trim_start();
+        let (date, remainder) = NaiveDate::parse_and_remainder(trimmed, DATE_FORMAT)
+            .map_err(|err| anyhow!("Failed to parse date: {} with error: {}", trimmed, err))?;
+
+        let mut consumed = input.len() - remainder.len();
+        let time_str = remainder.trim_start();
+        let mut time = None;
+        if time_str.len() < remainder.len()
+            && let Ok((parsed, time_remainder)) =
+                NaiveTime::parse_and_remainder(time_str, time_format)
+        {
+            time = Some(parsed);
+            consumed = input.len() - time_remainder.len();
+        }
+
+        Ok((TaskDate::new(date, time), consumed))
+    }
+
+    // Resolves the local date and time in the given timezone to a single instant.
+    // Ambiguous local times (when clocks fall back) resolve to the earlier instant.
+    // Nonexistent local times (when clocks spring forward) are shifted forward by
+    // the length of the gap, so 02:30 during a 1 hour gap becomes 03:30.
+    pub fn to_utc(self, tz: &chrono_tz::Tz) -> DateTime<Utc> {
+        let naive = self.naive();
+        match naive.and_local_timezone(*tz) {
+            LocalResult::Single(dt) => dt.to_utc(),
+            LocalResult::Ambiguous(earliest, _) => earliest.to_utc(),
+            LocalResult::None => {
+                // Use the offset in effect before the transition
+                let before = tz
+                    .offset_from_utc_datetime(&(naive - Duration::days(1)))
+                    .fix();
+                (naive - before).and_utc()
+            }
+        }
+    }
+
+    // Converts an instant into the local date and time of the given timezone.
+    // The time is omitted when the instant is the one a bare date resolves to.
+    pub fn from_utc(value: DateTime<Utc>, tz: &chrono_tz::Tz) -> Self {
+        let naive = value.with_timezone(tz).naive_local();
+        let date_only = TaskDate::new(naive.date(), None);
+        if date_only.to_utc(tz) == value {
+            return date_only;
+        }
+        TaskDate::new(naive.date(), Some(naive.time()))
+    }
+
+    pub fn to_timestamp(self, tz: &chrono_tz::Tz) -> String {
+        self.to_utc(tz).timestamp().to_string()
+    }
+
+    pub fn from_timestamp(timestamp: &str, tz: &chrono_tz::Tz) -> Self {
+        let ts = timestamp.parse::<i64>().expect("Could not parse timestamp");
+        TaskDate::from_utc(
+            DateTime::from_timestamp(ts, 0).expect("Invalid timestamp"),
+            tz,
+        )
+    }
+}
+
+impl From<NaiveDate> for TaskDate {
+    fn from(date: NaiveDate) -> Self {
+        TaskDate::new(date, None)
+    }
+}
+
+impl FromStr for TaskDate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (date, consumed) = TaskDate::parse_prefix(s, DEFAULT_TIME_FORMAT)?;
+        if !s[consumed..].trim().is_empty() {
+            return Err(anyhow!("Unexpected trailing characters in date: {}", s));
+        }
+        Ok(date)
+    }
+}
+
+impl Display for TaskDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(DEFAULT_TIME_FORMAT))
+    }
+}
+
+// Writes a duration in whole minutes, e.g. 2h15m, 2h or 45m
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h{minutes}m"),
+    }
+}
+
+// Writes a duration down to the second, leaving out the seconds when there
+// are none, e.g. 2h15m, 1m30s or 30s
+pub fn format_precise_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    match (seconds / 60, seconds % 60) {
+        (_, 0) => format_duration(duration),
+        (0, seconds) => format!("{seconds}s"),
+        (_, seconds) => format!("{}{seconds}s", format_duration(duration)),
+    }
+}
+
+// Parses a duration written by format_duration or format_precise_duration from
+// the start of the input, returning it along with the number of bytes consumed
+pub fn parse_duration_prefix(input: &str) -> Option<(Duration, usize)> {
+    let trimmed = input.trim_start();
+    let mut consumed = input.len() - trimmed.len();
+    let mut rest = trimmed;
+    let mut total = None;
+    for (unit, seconds) in [('h', 3600), ('m', 60), ('s', 1)] {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 || !rest[digits..].starts_with(unit) {
+            continue;
+        }
+        let value: i64 = rest[..digits].parse().ok()?;
+        total = Some(total.unwrap_or(Duration::zero()) + Duration::seconds(value * seconds));
+        consumed += digits + 1;
+        rest = &rest[digits + 1..];
+    }
+    total.map(|total| (total, consumed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_prefix() {
+        let (date, consumed) = TaskDate::parse_prefix(" 2025-06-13 14:00 ⏳", "%H:%M").unwrap();
+        assert_eq!(date, "2025-06-13 14:00".parse().unwrap());
+        assert_eq!(consumed, 17);
+
+        let (date, consumed) =
+            TaskDate::parse_prefix(" 2025-06-13 ⏳ 2025-06-14", "%H:%M").unwrap();
+        assert_eq!(date.time, None);
+        assert_eq!(consumed, 11);
+
+        let (date, _) = TaskDate::parse_prefix("2025-06-13 2:30 PM", "%I:%M %p").unwrap();
+        assert_eq!(date.time, NaiveTime::from_hms_opt(14, 30, 0));
+
+        assert!(TaskDate::parse_prefix("25", "%H:%M").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_and_missing_times() {
+        let new_york = chrono_tz::America::New_York;
+
+        // Clocks fall back at 02:00, so 01:30 happens twice. The earlier one is used.
+        let ambiguous: TaskDate = "2025-11-02 01:30".parse().unwrap();
+        assert_eq!(
+            ambiguous.to_utc(&new_york),
+            DateTime::parse_from_rfc3339("2025-11-02T05:30:00Z").unwrap()
+        );
+
+        // Clocks spring forward at 02:00, so 02:30 never happens and is moved to 03:30
+        let missing: TaskDate = "2025-03-09 02:30".parse().unwrap();
+        assert_eq!(
+            missing.to_utc(&new_york),
+            DateTime::parse_from_rfc3339("2025-03-09T07:30:00Z").unwrap()
+        );
+        assert_eq!(
+            TaskDate::from_utc(missing.to_utc(&new_york), &new_york),
+            "2025-03-09 03:30".parse().unwrap()
+        );
+
+        // Chile springs forward at midnight, so the day starts at 01:00
+        let santiago = chrono_tz::America::Santiago;
+        let date: TaskDate = "2025-09-07".parse().unwrap();
+        assert_eq!(
+            date.to_utc(&santiago),
+            DateTime::parse_from_rfc3339("2025-09-07T04:00:00Z").unwrap()
+        );
+        assert_eq!(TaskDate::from_utc(date.to_utc(&santiago), &santiago), date);
+    }
+
+    #[test]
+    fn test_timezone_matrix() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let noon = NaiveTime::from_hms_opt(12, 0, 0);
+        for tz in chrono_tz::TZ_VARIANTS {
+            for date in start.iter_days().take(731) {
+                for time in [None, noon] {
+                    let task_date = TaskDate::new(date, time);
+                    let round_trip = TaskDate::from_utc(task_date.to_utc(&tz), &tz);
+                    assert_eq!(round_trip, task_date, "{tz}");
+
+                    let timestamp = task_date.to_timestamp(&tz);
+                    assert_eq!(TaskDate::from_timestamp(&timestamp, &tz), task_date, "{tz}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let date: TaskDate = "2025-06-13 09:05".parse().unwrap();
+        assert_eq!(date.format("%H:%M"), "2025-06-13 09:05");
+        assert_eq!(date.format("%I:%M %p"), "2025-06-13 09:05 AM");
+
+        let date: TaskDate = "2025-06-13".parse().unwrap();
+        assert_eq!(date.format("%H:%M"), "2025-06-13");
+    }
+
+    #[test]
+    fn test_durations() {
+        for (minutes, text) in [(135, "2h15m"), (120, "2h"), (45, "45m"), (0, "0m")] {
+            assert_eq!(format_duration(Duration::minutes(minutes)), text);
+            assert_eq!(
+                parse_duration_prefix(text),
+                Some((Duration::minutes(minutes), text.len()))
+            );
+        }
+        assert_eq!(format_duration(Duration::seconds(119)), "1m");
+
+        for (seconds, text) in [(90, "1m30s"), (30, "30s"), (3630, "1h30s"), (3600, "1h")] {
+            assert_eq!(format_precise_duration(Duration::seconds(seconds)), text);
+            assert_eq!(
+                parse_duration_prefix(text),
+                Some((Duration::seconds(seconds), text.len()))
+            );
+        }
+
+        assert_eq!(
+            parse_duration_prefix(" 1h5m 🔼"),
+            Some((Duration::minutes(65), 5))
+        );
+        assert_eq!(parse_duration_prefix(" soon"), None);
+        assert_eq!(parse_duration_prefix("15"), None);
+    }
+}
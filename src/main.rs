@@ -1,10 +1,16 @@
 use anyhow::{Context, Result, anyhow};
 use colored::Colorize;
-use grep::{regex::RegexMatcher, searcher::Searcher, searcher::sinks};
 use ignore::{WalkBuilder, types::TypesBuilder};
+use std::rc::Rc;
 use tasksync::{TaskWarriorSync, UpdateContext, update_obsidian_tasks};
 
+mod attribute;
 mod config;
+mod datetime;
+mod note;
+mod report;
+mod scanner;
+mod taskdata;
 mod taskparser;
 mod tasksync;
 
@@ -13,10 +19,35 @@ mod testutil;
 
 fn main() -> Result<()> {
     let cfg = config::get();
+    if matches!(cfg.direction, config::Direction::Report { .. })
+        && !cfg.settings.mapping.tracks_active()
+    {
+        return Err(anyhow!(
+            "Time can't be tracked while mapping.start stores 🛫 in the start attribute"
+        ));
+    }
+
+    if cfg.backend == config::Backend::Taskwarrior2 && cfg.sync.server_config()?.is_some() {
+        return Err(anyhow!(
+            "Taskwarrior 2.x data can't be synced with a taskchampion sync server"
+        ));
+    }
+
+    let folder_filter = match &cfg.vault_path {
+        Some(vault_path) => Some(cfg.settings.filter.overrides(vault_path)?),
+        None => None,
+    };
 
     let mut paths = Vec::new();
-    if let Some(file_path) = cfg.file_path {
-        paths.push(file_path);
+    if let Some(file_path) = cfg.file_path.clone() {
+        let excluded = folder_filter
+            .as_ref()
+            .is_some_and(|filter| filter.matched(&file_path, false).is_ignore());
+        if excluded {
+            println!("{}", format!("Excluded: {}", file_path.display()).yellow());
+        } else {
+            paths.push(file_path);
+        }
     } else {
         let md_types = TypesBuilder::new()
             .add_defaults()
@@ -25,6 +56,7 @@ fn main() -> Result<()> {
             .expect("Failed to build type matcher");
         let walk_paths = WalkBuilder::new(cfg.vault_path.as_ref().expect("No vault set"))
             .types(md_types)
+            .overrides(folder_filter.expect("Vault filter should be built"))
             .build()
             .filter_map(Result::ok)
             .filter(|entry| entry.file_type().map_or(false, |ft| ft.is_file()))
@@ -32,40 +64,125 @@ fn main() -> Result<()> {
         paths.extend(walk_paths);
     }
 
+    // The whole run shares one replica, so its changes can be undone together.
+    // Migrating only rewrites the notes.
+    let mut task_db = match cfg.direction {
+        config::Direction::Migrate { .. } => None,
+        _ => Some(
+            TaskWarriorSync::new(&cfg.task_path, cfg.backend, &cfg.tz)
+                .context("Failed to open task database")?,
+        ),
+    };
+    // Pick up changes made on other machines before reading the tasks
+    if let Some(sync) = task_db.as_mut() {
+        sync_server(&cfg, sync);
+    }
+
     let mut errors = 0;
+    let mut total_unlinked = 0;
+    let mut total_changed = 0;
+    let mut moves = Vec::new();
+    let mut time_report = report::TimeReport::default();
     for path in paths {
         println!("{}", format!("Processing: {}", &path.display()).blue());
-        let task_matcher = RegexMatcher::new_line_matcher(r"- \[(?: |-|x)\] .*")
-            .expect("Failed to build regex matcher");
+        let note = note::Note::load(&path, cfg.vault_path.as_deref())?;
+        if let config::Direction::Migrate { to } = &cfg.direction {
+            let format = to.unwrap_or(cfg.settings.markdown.uuid_format);
+            match migrate_note(&note, &cfg.settings.markdown, &format) {
+                Ok(0) => {}
+                Ok(count) => println!("  {}", format!("Migrated {count} tasks").green()),
+                Err(err) => {
+                    println!("  {}", format!("{err:#}").red());
+                    errors += 1;
+                }
+            }
+            continue;
+        }
+        let defaults = match note.defaults() {
+            Ok(defaults) => Rc::new(defaults),
+            Err(err) => {
+                println!("  {}", format!("{err:#}").red());
+                errors += 1;
+                continue;
+            }
+        };
+        let inferred_project = note.project(&cfg.settings.project);
+        let sync = task_db
+            .as_mut()
+            .expect("Only migrations run without the task database");
+        if let config::Direction::Unlink { tasks, dry_run } = &cfg.direction {
+            let context = NoteContext {
+                note: &note,
+                defaults: &defaults,
+                inferred_project: &inferred_project,
+            };
+            match unlink_note(&cfg, sync, &context, tasks, *dry_run) {
+                Ok((unlinked, changed)) => {
+                    total_unlinked += unlinked;
+                    total_changed += changed;
+                }
+                Err(err) => {
+                    println!("  {}", format!("{err:#}").red());
+                    errors += 1;
+                }
+            }
+            continue;
+        }
+        if !defaults.sync {
+            println!("  {}", "Sync disabled in frontmatter, skipping".yellow());
+            continue;
+        }
+        if let config::Direction::Report {
+            report: config::Report::Time,
+        } = &cfg.direction
+        {
+            let context = NoteContext {
+                note: &note,
+                defaults: &defaults,
+                inferred_project: &inferred_project,
+            };
+            if let Err(err) = report_note_time(&cfg, sync, &context, &mut time_report) {
+                println!("  {}", format!("{err:#}").red());
+                errors += 1;
+            }
+            continue;
+        }
         let mut lines = Vec::new();
-        let sink = sinks::UTF8(|offset, text| {
-            let task_option = taskparser::parse(text.to_string(), &cfg.tz);
-            if let Some(task) = task_option {
+        for task_line in scanner::scan(&note.contents, &cfg.settings.markdown) {
+            let text = task_line.text;
+            if !taskparser::has_global_filter(&text, &cfg.settings) {
+                continue;
+            }
+            let task_option = taskparser::parse(text.clone(), &cfg.tz, &cfg.settings);
+            if let Some(mut task) = task_option {
+                task.inferred_project = inferred_project.clone();
+                task.defaults = defaults.clone();
+                if !task.passes_filter() {
+                    continue;
+                }
                 lines.push(UpdateContext {
-                    line: usize::try_from(offset - 1).expect("Offset should fit"),
+                    line: task_line.line,
                     task,
                 });
             } else {
                 println!("  {}", format!("{} {}", "Failed to parse:", text).red());
             }
-            Ok(true)
-        });
-        Searcher::new()
-            .search_path(task_matcher, path.clone(), sink)
-            .context("Failed during search")?;
+        }
 
         let mut updates = Vec::new();
         for line in lines.iter_mut() {
-            let mut sync = TaskWarriorSync::new(&cfg.task_path, &cfg.tz)
-                .context("Failed to open task database")
-                .expect("Should be able to access task database");
+            if let Err(err) = sync.resolve_short_id(&mut line.task) {
+                println!("  {}", format!("{err:#}").red());
+                continue;
+            }
             if cfg.direction == config::Direction::MdToTc {
                 let update = sync.md_to_tc(&mut line.task, path.clone(), cfg.vault_path.clone());
                 if update.is_ok() && update.unwrap() {
                     updates.push(line.clone());
                 }
+                moves.append(&mut sync.moves);
             } else {
-                let update = sync.tc_to_md(&line.task, &cfg.tz);
+                let update = sync.tc_to_md(&line.task);
                 if let Some(task) = update {
                     let updated_line = UpdateContext { task, ..*line };
                     updates.push(updated_line);
@@ -77,9 +194,194 @@ fn main() -> Result<()> {
             errors += 1;
         }
     }
+    if !moves.is_empty() {
+        println!("{}", format!("Moved {} tasks:", moves.len()).green());
+        for task_move in &moves {
+            println!(
+                "  {}",
+                format!(
+                    "{}: {} -> {}",
+                    task_move.description, task_move.from, task_move.to
+                )
+                .green()
+            );
+        }
+    }
+    // Dry runs and reports leave the database as it was, so there is nothing to
+    // renumber or send to the server
+    if let Some(sync) = task_db.as_mut()
+        && sync.has_changes()
+    {
+        if let Err(err) = sync.rebuild_working_set() {
+            println!("{}", format!("{err:#}").red());
+            errors += 1;
+        }
+        sync_server(&cfg, sync);
+    }
+    if let config::Direction::Report {
+        report: config::Report::Time,
+    } = &cfg.direction
+    {
+        time_report.print();
+    }
+    if let config::Direction::Unlink { tasks, dry_run } = &cfg.direction {
+        let prefix = if *dry_run { "Would unlink" } else { "Unlinked" };
+        let action = match tasks {
+            config::UnlinkAction::Keep => String::new(),
+            config::UnlinkAction::Complete => format!(", {total_changed} tasks completed"),
+            config::UnlinkAction::Delete => format!(", {total_changed} tasks deleted"),
+        };
+        println!(
+            "{}",
+            format!("{prefix} {total_unlinked} tasks{action}").green()
+        );
+    }
     if errors > 0 {
         return Err(anyhow!("{errors} files failed to update"));
     } else {
         return Ok(());
     }
 }
+
+// Syncs the task database with the configured server, if any. Failures only
+// warn, since the changes reach the server with the next successful sync.
+fn sync_server(cfg: &config::Config, sync: &mut TaskWarriorSync) {
+    let result = cfg.sync.server_config().and_then(|server| {
+        let Some(server) = server else {
+            return Ok(false);
+        };
+        if let Some(server_dir) = &cfg.sync.server_dir {
+            std::fs::create_dir_all(server_dir)
+                .with_context(|| format!("Failed to create {}", server_dir.display()))?;
+        }
+        let mut server = server
+            .into_server()
+            .context("Failed to open the sync server")?;
+        sync.sync_server(&mut server)?;
+        Ok(true)
+    });
+    match result {
+        Ok(true) => println!("{}", "Synced with the server".green()),
+        Ok(false) => {}
+        Err(err) => println!("{}", format!("{err:#}").yellow()),
+    }
+}
+
+// What the tasks of a note inherit from it
+struct NoteContext<'a> {
+    note: &'a note::Note,
+    defaults: &'a Rc<note::NoteDefaults>,
+    inferred_project: &'a Option<String>,
+}
+
+// Removes the uuid markers from the tasks of a note and completes or deletes
+// the tasks they linked to. Returns how many tasks were unlinked and how many
+// taskwarrior tasks were changed.
+fn unlink_note(
+    cfg: &config::Config,
+    sync: &mut TaskWarriorSync,
+    context: &NoteContext,
+    action: &config::UnlinkAction,
+    dry_run: bool,
+) -> Result<(usize, usize)> {
+    let mut updates = Vec::new();
+    let mut changed = 0;
+    for task_line in scanner::scan(&context.note.contents, &cfg.settings.markdown) {
+        if !taskparser::has_global_filter(&task_line.text, &cfg.settings) {
+            continue;
+        }
+        let Some(mut task) = taskparser::parse(task_line.text, &cfg.tz, &cfg.settings) else {
+            continue;
+        };
+        if task.uuid.is_none() && task.short_id.is_none() {
+            continue;
+        }
+        task.inferred_project = context.inferred_project.clone();
+        task.defaults = context.defaults.clone();
+
+        println!("  {}", task.to_string().blue());
+        match sync.resolve_short_id(&mut task) {
+            Ok(()) => {
+                let uuid = task.uuid.expect("Resolved tasks have a uuid");
+                if sync.unlink(uuid, action, dry_run)? {
+                    changed += 1;
+                }
+            }
+            Err(err) => println!("      {}", format!("{err:#}").yellow()),
+        }
+
+        task.uuid = None;
+        task.short_id = None;
+        // Only the block ids sharptask generated, the user's own stay
+        task.block_id
+            .take_if(|block_id| block_id.starts_with("tw-"));
+        updates.push(UpdateContext {
+            line: task_line.line,
+            task,
+        });
+    }
+    if !dry_run && !updates.is_empty() {
+        update_obsidian_tasks(&context.note.path, &updates)?;
+    }
+    Ok((updates.len(), changed))
+}
+
+// Adds the time tracked on the synced tasks of a note to the report
+fn report_note_time(
+    cfg: &config::Config,
+    sync: &mut TaskWarriorSync,
+    context: &NoteContext,
+    report: &mut report::TimeReport,
+) -> Result<()> {
+    let note_name = context
+        .note
+        .relative_path
+        .as_ref()
+        .unwrap_or(&context.note.path)
+        .display()
+        .to_string();
+    for task_line in scanner::scan(&context.note.contents, &cfg.settings.markdown) {
+        if !taskparser::has_global_filter(&task_line.text, &cfg.settings) {
+            continue;
+        }
+        let Some(mut task) = taskparser::parse(task_line.text, &cfg.tz, &cfg.settings) else {
+            continue;
+        };
+        if task.uuid.is_none() && task.short_id.is_none() {
+            continue;
+        }
+        task.inferred_project = context.inferred_project.clone();
+        task.defaults = context.defaults.clone();
+        if !task.passes_filter() {
+            continue;
+        }
+        if let Err(err) = sync.resolve_short_id(&mut task) {
+            println!("  {}", format!("{err:#}").yellow());
+            continue;
+        }
+        let uuid = task.uuid.expect("Resolved tasks have a uuid");
+        report.add(&note_name, &task, sync.tracked_time(uuid)?);
+    }
+    Ok(())
+}
+
+// Rewrites the uuid markers of a note in the given format, returning how many
+// tasks were changed
+fn migrate_note(
+    note: &note::Note,
+    markdown: &config::Markdown,
+    format: &config::UuidFormat,
+) -> Result<usize> {
+    let file_lines: Vec<&str> = note.contents.lines().collect();
+    let updates: Vec<(usize, String)> = scanner::scan(&note.contents, markdown)
+        .iter()
+        .filter_map(|task_line| {
+            taskparser::replace_uuid_marker(file_lines[task_line.line], format)
+                .map(|line| (task_line.line, line))
+        })
+        .collect();
+    if !updates.is_empty() {
+        tasksync::update_lines(&note.path, &updates)?;
+    }
+    Ok(updates.len())
+}
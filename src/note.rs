@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ProjectInference, ProjectSource};
+use crate::taskparser::Priority;
+
+const DEFAULTS_KEY: &str = "sharptask";
+
+// A markdown note in the vault along with the context its tasks inherit
+#[derive(Debug, Default, Clone)]
+pub struct Note {
+    pub path: PathBuf,
+    // Path of the note relative to the vault, if it is inside one
+    pub relative_path: Option<PathBuf>,
+    pub frontmatter: Mapping,
+    pub contents: String,
+}
+
+// Defaults for every task in a note, set in the frontmatter under the
+// sharptask key. They are synced to taskchampion but never written into the
+// task lines.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NoteDefaults {
+    pub tags: Vec<String>,
+    pub project: Option<String>,
+    pub priority: Option<Priority>,
+    // Notes with sync turned off are skipped completely
+    pub sync: bool,
+}
+
+impl Default for NoteDefaults {
+    fn default() -> Self {
+        NoteDefaults {
+            tags: Vec::new(),
+            project: None,
+            priority: None,
+            sync: true,
+        }
+    }
+}
+
+impl Note {
+    pub fn load<P: AsRef<Path>>(path: P, vault_path: Option<&Path>) -> Result<Note> {
+        let contents = fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read {}", path.as_ref().display()))?;
+        Ok(Note::from_contents(path, vault_path, &contents))
+    }
+
+    pub fn from_contents<P: AsRef<Path>>(
+        path: P,
+        vault_path: Option<&Path>,
+        contents: &str,
+    ) -> Note {
+        let relative_path = vault_path
+            .and_then(|vault| path.as_ref().strip_prefix(vault).ok())
+            .map(|relative| relative.to_path_buf());
+        Note {
+            path: path.as_ref().to_path_buf(),
+            relative_path,
+            frontmatter: parse_frontmatter(contents),
+            contents: contents.to_string(),
+        }
+    }
+
+    pub fn defaults(&self) -> Result<NoteDefaults> {
+        match self.frontmatter.get(DEFAULTS_KEY) {
+            Some(value) => serde_yaml::from_value(value.clone()).with_context(|| {
+                format!(
+                    "Invalid {} frontmatter in {}",
+                    DEFAULTS_KEY,
+                    self.path.display()
+                )
+            }),
+            None => Ok(NoteDefaults::default()),
+        }
+    }
+
+    // Finds the project for the note's tasks. A project in the note defaults
+    // wins, otherwise the first source that has one is used.
+    pub fn project(&self, inference: &ProjectInference) -> Option<String> {
+        if let Ok(NoteDefaults {
+            project: Some(project),
+            ..
+        }) = self.defaults()
+        {
+            return Some(project);
+        }
+        inference.sources.iter().find_map(|source| match source {
+            ProjectSource::Folder => {
+                let folder = self.relative_path.as_ref()?.parent()?;
+                let levels: Vec<String> = folder
+                    .components()
+                    .map(|level| level.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                Some(levels.join(&inference.separator)).filter(|project| !project.is_empty())
+            }
+            ProjectSource::Frontmatter => match self.frontmatter.get(&inference.key)? {
+                Value::String(project) if !project.is_empty() => Some(project.clone()),
+                Value::Number(project) => Some(project.to_string()),
+                _ => None,
+            },
+            ProjectSource::Note => self
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+        })
+    }
+}
+
+// Reads the YAML frontmatter at the very start of a note. Notes without valid
+// frontmatter get an empty mapping.
+fn parse_frontmatter(contents: &str) -> Mapping {
+    let mut lines = contents.lines();
+    if lines.next().map(|line| line.trim_end()) != Some("---") {
+        return Mapping::new();
+    }
+
+    let mut yaml = String::new();
+    for line in lines {
+        if line.trim_end() == "---" || line.trim_end() == "..." {
+            return serde_yaml::from_str(&yaml).unwrap_or_default();
+        }
+        yaml.push_str(line);
+        yaml.push('\n');
+    }
+    Mapping::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_frontmatter() {
+        let note = Note::from_contents(
+            "/vault/note.md",
+            None,
+            "---\nproject: Acme\ntags: [a, b]\n---\n- [ ] Task\n",
+        );
+        assert_eq!(note.frontmatter.get("project").unwrap(), "Acme");
+
+        let note = Note::from_contents("/vault/note.md", None, "- [ ] Task\n---\n");
+        assert!(note.frontmatter.is_empty());
+
+        let note = Note::from_contents("/vault/note.md", None, "---\nproject: Acme\n");
+        assert!(note.frontmatter.is_empty());
+    }
+
+    #[test]
+    fn test_project_inference() {
+        let vault = PathBuf::from("/vault");
+        let note = Note::from_contents(
+            "/vault/Work/ClientA/Meeting.md",
+            Some(&vault),
+            "---\nproject: Acme\n---\n",
+        );
+        let mut inference = ProjectInference::default();
+        assert_eq!(note.project(&inference), None);
+
+        inference.sources = vec![ProjectSource::Folder];
+        assert_eq!(note.project(&inference).unwrap(), "Work.ClientA");
+
+        inference.sources = vec![ProjectSource::Frontmatter, ProjectSource::Folder];
+        assert_eq!(note.project(&inference).unwrap(), "Acme");
+
+        inference.sources = vec![ProjectSource::Note];
+        assert_eq!(note.project(&inference).unwrap(), "Meeting");
+
+        // Notes at the root of the vault have no folder project
+        let note = Note::from_contents("/vault/Inbox.md", Some(&vault), "");
+        inference.sources = vec![ProjectSource::Folder, ProjectSource::Note];
+        assert_eq!(note.project(&inference).unwrap(), "Inbox");
+    }
+
+    #[test]
+    fn test_defaults() {
+        let note = Note::from_contents(
+            "/vault/Work/Acme.md",
+            Some(Path::new("/vault")),
+            "---\nsharptask:\n  tags: [client]\n  project: Acme\n  priority: high\n---\n",
+        );
+        let defaults = note.defaults().unwrap();
+        assert_eq!(defaults.tags, ["client"]);
+        assert_eq!(defaults.priority, Some(Priority::High));
+        assert!(defaults.sync);
+
+        // The project default wins over any inferred project
+        let inference = ProjectInference {
+            sources: vec![ProjectSource::Folder],
+            ..ProjectInference::default()
+        };
+        assert_eq!(note.project(&inference).unwrap(), "Acme");
+
+        let note = Note::from_contents("/vault/a.md", None, "---\nsharptask: {sync: false}\n---\n");
+        assert!(!note.defaults().unwrap().sync);
+
+        let note = Note::from_contents("/vault/a.md", None, "---\ntitle: A\n---\n");
+        assert_eq!(note.defaults().unwrap(), NoteDefaults::default());
+
+        let note = Note::from_contents(
+            "/vault/a.md",
+            None,
+            "---\nsharptask: {priority: urgent}\n---\n",
+        );
+        assert!(note.defaults().is_err());
+    }
+}
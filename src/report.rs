@@ -0,0 +1,114 @@
+use chrono::Duration;
+use colored::Colorize;
+use std::collections::BTreeMap;
+
+use crate::datetime;
+use crate::taskparser::ObsidianTask;
+
+const NO_PROJECT: &str = "(none)";
+
+// Time tracked in taskwarrior, added up across the vault
+#[derive(Debug, Default)]
+pub struct TimeReport {
+    pub notes: BTreeMap<String, Duration>,
+    pub projects: BTreeMap<String, Duration>,
+    pub tags: BTreeMap<String, Duration>,
+}
+
+impl TimeReport {
+    // Counts the time of a task towards its note, its project and each of its
+    // tags, so the tag totals can add up to more than the overall total
+    pub fn add(&mut self, note: &str, task: &ObsidianTask, tracked: Duration) {
+        if tracked.is_zero() {
+            return;
+        }
+        *self.notes.entry(note.to_string()).or_default() += tracked;
+        let project = task.effective_project().unwrap_or(NO_PROJECT);
+        *self.projects.entry(project.to_string()).or_default() += tracked;
+        for tag in task.effective_tags() {
+            *self.tags.entry(tag).or_default() += tracked;
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.notes.values().copied().sum()
+    }
+
+    pub fn print(&self) {
+        for (title, totals) in [
+            ("Notes", &self.notes),
+            ("Projects", &self.projects),
+            ("Tags", &self.tags),
+        ] {
+            if totals.is_empty() {
+                continue;
+            }
+            println!("{}", title.green());
+            for (name, tracked) in sorted(totals) {
+                println!("  {:>8}  {}", datetime::format_duration(tracked), name);
+            }
+        }
+        println!(
+            "{}",
+            format!("Total: {}", datetime::format_duration(self.total())).green()
+        );
+    }
+}
+
+// Longest first, then by name
+fn sorted(totals: &BTreeMap<String, Duration>) -> Vec<(&str, Duration)> {
+    let mut sorted: Vec<(&str, Duration)> = totals
+        .iter()
+        .map(|(name, tracked)| (name.as_str(), *tracked))
+        .collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::taskparser::ObsidianTaskBuilder;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_time_report() {
+        let mut report = TimeReport::default();
+        let task = ObsidianTaskBuilder::new()
+            .description("Write #work #client")
+            .tags(&["work", "client"])
+            .project_str("Acme")
+            .build();
+        report.add("Work/Acme.md", &task, Duration::minutes(90));
+        let task = ObsidianTaskBuilder::new()
+            .description("Call #work")
+            .tags(&["work"])
+            .build();
+        report.add("Inbox.md", &task, Duration::minutes(15));
+        report.add("Inbox.md", &task, Duration::zero());
+
+        assert_eq!(report.total(), Duration::minutes(105));
+        assert_eq!(
+            sorted(&report.notes),
+            [
+                ("Work/Acme.md", Duration::minutes(90)),
+                ("Inbox.md", Duration::minutes(15))
+            ]
+        );
+        assert_eq!(
+            sorted(&report.projects),
+            [
+                ("Acme", Duration::minutes(90)),
+                (NO_PROJECT, Duration::minutes(15))
+            ]
+        );
+        assert_eq!(
+            sorted(&report.tags),
+            [
+                ("work", Duration::minutes(105)),
+                ("client", Duration::minutes(90))
+            ]
+        );
+    }
+}
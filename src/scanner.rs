@@ -0,0 +1,303 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::config::Markdown;
+use crate::taskparser;
+
+// A line of a note that holds a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskLine {
+    // Zero based line number within the note
+    pub line: usize,
+    // The task with any blockquote prefix removed
+    pub text: String,
+}
+
+static TASK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(?:[-*+]|\d{1,9}[.)]) \[(?<status>[^\]])\] ").unwrap());
+static LIST_ITEM_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(?:[-*+]|\d+[.)])(?:\s|$)").unwrap());
+static FENCE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[ \t]*(?<fence>`{3,}|~{3,})").unwrap());
+static QUOTE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^ {0,3}(?:> ?)+").unwrap());
+
+#[derive(Debug, PartialEq, Eq)]
+enum Comment {
+    Html,
+    Obsidian,
+}
+
+// Finds the task lines in a note, skipping anything obsidian wouldn't render as
+// a task: code blocks, comments and optionally frontmatter and blockquotes
+pub fn scan(contents: &str, settings: &Markdown) -> Vec<TaskLine> {
+    let mut tasks = Vec::new();
+    let mut lines = contents.lines().enumerate().peekable();
+
+    // Frontmatter is only recognised at the very start of the note, and only
+    // when it is closed again, as note::parse_frontmatter reads it
+    let closes_frontmatter = |line: &str| line.trim_end() == "---" || line.trim_end() == "...";
+    if settings.skip_frontmatter
+        && lines
+            .peek()
+            .is_some_and(|(_, line)| line.trim_end() == "---")
+        && lines
+            .clone()
+            .skip(1)
+            .any(|(_, line)| closes_frontmatter(line))
+    {
+        lines.next();
+        for (_, line) in lines.by_ref() {
+            if closes_frontmatter(line) {
+                break;
+            }
+        }
+    }
+
+    // An open fence along with how far its closing fence may be indented
+    let mut fence: Option<(String, usize)> = None;
+    let mut comment: Option<Comment> = None;
+    let mut in_list = false;
+    // Where the text of the last list item starts, fences can be indented up
+    // to three spaces past it
+    let mut list_indent = 0;
+    let mut indented_code = false;
+    let mut previous_blank = true;
+    for (index, line) in lines {
+        // Blockquotes and callouts nest everything after their prefix
+        let quote = QUOTE_RE.find(line).map_or(0, |prefix| prefix.end());
+        let content = &line[quote..];
+        let blank = content.trim().is_empty();
+
+        let indent = indent_width(content);
+        if let Some((open, max_indent)) = &fence {
+            if let Some(caps) = FENCE_RE.captures(content)
+                && indent <= *max_indent
+                && caps["fence"].starts_with(&open[..1])
+                && caps["fence"].len() >= open.len()
+                && content.trim()[caps["fence"].len()..].is_empty()
+            {
+                fence = None;
+            }
+            previous_blank = blank;
+            continue;
+        }
+
+        let starts_in_comment = comment.is_some();
+        comment = update_comment(content, comment);
+        if starts_in_comment {
+            previous_blank = blank;
+            continue;
+        }
+
+        let max_indent = if in_list { list_indent + 3 } else { 3 };
+        if let Some(caps) = FENCE_RE.captures(content)
+            && indent <= max_indent
+        {
+            fence = Some((caps["fence"].to_string(), max_indent));
+            in_list = in_list && indent > 0;
+            previous_blank = false;
+            continue;
+        }
+
+        // Indented lines are code unless they continue a list
+        if !blank {
+            if indent >= 4 && (indented_code || (previous_blank && !in_list)) {
+                indented_code = true;
+                previous_blank = false;
+                continue;
+            }
+            indented_code = false;
+            if let Some(item) = LIST_ITEM_RE.find(content) {
+                in_list = true;
+                list_indent = indent + item.as_str().trim_start().len();
+            } else if indent == 0 {
+                in_list = false;
+            }
+        }
+        previous_blank = blank;
+
+        if quote > 0 && !settings.quoted_tasks {
+            continue;
+        }
+        if let Some(caps) = TASK_RE.captures(content)
+            && caps["status"]
+                .chars()
+                .next()
+                .is_some_and(|status| taskparser::is_task_status(status, settings))
+        {
+            tasks.push(TaskLine {
+                line: index,
+                text: content.to_string(),
+            });
+        }
+    }
+    tasks
+}
+
+// Follows the comment openers and closers on a line, returning the comment
+// that is still open at the end of it
+fn update_comment(line: &str, mut comment: Option<Comment>) -> Option<Comment> {
+    let mut rest = line;
+    loop {
+        let (marker, next) = match comment {
+            Some(Comment::Html) => ("-->", None),
+            Some(Comment::Obsidian) => ("%%", None),
+            None => {
+                let html = rest.find("<!--");
+                let obsidian = rest.find("%%");
+                match (html, obsidian) {
+                    (Some(html), Some(obsidian)) if obsidian < html => {
+                        ("%%", Some(Comment::Obsidian))
+                    }
+                    (Some(_), _) => ("<!--", Some(Comment::Html)),
+                    (None, Some(_)) => ("%%", Some(Comment::Obsidian)),
+                    (None, None) => return None,
+                }
+            }
+        };
+        match rest.find(marker) {
+            Some(position) => {
+                rest = &rest[position + marker.len()..];
+                comment = next;
+            }
+            None => return comment,
+        }
+    }
+}
+
+fn indent_width(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4 - width % 4,
+            _ => break,
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn lines(contents: &str, settings: &Markdown) -> Vec<usize> {
+        scan(contents, settings)
+            .iter()
+            .map(|task| task.line)
+            .collect()
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let contents = "- [ ] Real\n\
+                        ````markdown\n\
+                        - [ ] Fenced\n\
+                        ```\n\
+                        - [ ] Still fenced\n\
+                        ````\n\
+                        ~~~\n\
+                        - [ ] Tilde fenced\n\
+                        ~~~\n\
+                        \n    - [ ] Indented code\n\
+                        \n\
+                        - [ ] Parent\n\
+                        \n    - [ ] Nested under a list\n\
+                        \t- [x] Tab nested\n\
+                        * [ ] Star\n\
+                        + [ ] Plus\n\
+                        1. [ ] Ordered\n\
+                        2) [ ] Ordered paren\n\
+                        - Not a task [ ]\n\
+                        - [/] Active\n\
+                        - [?] Unknown status\n";
+        assert_eq!(
+            lines(contents, &Markdown::default()),
+            [0, 12, 14, 15, 16, 17, 18, 19, 21]
+        );
+    }
+
+    #[test]
+    fn test_fence_in_list() {
+        let contents = "- Example\n\
+                        \n    ```markdown\n    - [ ] Fenced example in a list\n    ```\n\
+                        - [ ] Real\n\
+                        Not a list\n\
+                        \n        ```\n\
+                        - [ ] After indented code\n";
+        assert_eq!(lines(contents, &Markdown::default()), [5, 9]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let contents = "- [ ] Real\n\
+                        %%\n\
+                        - [ ] Obsidian comment\n\
+                        %%\n\
+                        <!-- - [ ] Inline html comment -->\n\
+                        <!--\n\
+                        - [ ] Html comment\n\
+                        -->\n\
+                        - [ ] After %%a comment%% on the line\n\
+                        %% - [ ] Inline obsidian comment %%\n";
+        assert_eq!(lines(contents, &Markdown::default()), [0, 8]);
+    }
+
+    #[test]
+    fn test_frontmatter() {
+        let contents = "---\n\
+                        template: |\n  - [ ] In frontmatter\n\
+                        ---\n\
+                        - [ ] Real\n";
+        assert_eq!(lines(contents, &Markdown::default()), [4]);
+
+        let settings = Markdown {
+            skip_frontmatter: false,
+            ..Markdown::default()
+        };
+        assert_eq!(lines(contents, &settings), [2, 4]);
+
+        // Without a closing delimiter there is no frontmatter to skip
+        let contents = "---\n\
+                        - [ ] First\n\
+                        - [ ] Second\n";
+        assert_eq!(lines(contents, &Markdown::default()), [1, 2]);
+    }
+
+    #[test]
+    fn test_quotes() {
+        let contents = "> [!todo] Callout\n\
+                        > - [ ] In a callout\n\
+                        >> - [ ] Nested quote\n\
+                        > ```\n\
+                        > - [ ] Quoted code\n\
+                        > ```\n\
+                        - [ ] Real\n";
+        assert_eq!(lines(contents, &Markdown::default()), [6]);
+
+        let settings = Markdown {
+            quoted_tasks: true,
+            ..Markdown::default()
+        };
+        let tasks = scan(contents, &settings);
+        assert_eq!(
+            tasks,
+            [
+                TaskLine {
+                    line: 1,
+                    text: String::from("- [ ] In a callout")
+                },
+                TaskLine {
+                    line: 2,
+                    text: String::from("- [ ] Nested quote")
+                },
+                TaskLine {
+                    line: 6,
+                    text: String::from("- [ ] Real")
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,588 @@
+use anyhow::{Context, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use taskchampion::storage::{Storage, StorageTxn, TaskMap};
+use taskchampion::{Error, Operation, Uuid};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const PENDING: &str = "pending.data";
+const COMPLETED: &str = "completed.data";
+const UNDO: &str = "undo.data";
+
+// Taskchampion storage kept in the data files of taskwarrior 2.x. Pending
+// tasks live in pending.data, where a task's id is its line number, and
+// completed and deleted tasks in completed.data. Every change is added to
+// undo.data so `task undo` can revert it.
+pub struct TaskDataStorage {
+    path: PathBuf,
+    data: Data,
+    // The tasks as they are in the files, along with their lines
+    saved: HashMap<Uuid, (TaskMap, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Data {
+    tasks: HashMap<Uuid, TaskMap>,
+    // The order the tasks were read or created in
+    order: Vec<Uuid>,
+    // The files don't keep any history, so operations only last for the run
+    operations: Vec<Operation>,
+    working_set: Vec<Option<Uuid>>,
+}
+
+impl TaskDataStorage {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if !path.is_dir() {
+            return Err(anyhow!(
+                "No taskwarrior data directory at {}",
+                path.display()
+            ));
+        }
+        let mut storage = TaskDataStorage {
+            path: path.to_path_buf(),
+            data: Data {
+                tasks: HashMap::new(),
+                order: Vec::new(),
+                operations: Vec::new(),
+                working_set: vec![None],
+            },
+            saved: HashMap::new(),
+        };
+        for file in [PENDING, COMPLETED] {
+            let file_path = path.join(file);
+            if !file_path.exists() {
+                continue;
+            }
+            let contents = fs::read_to_string(&file_path)
+                .with_context(|| format!("Failed to read {}", file_path.display()))?;
+            for (index, line) in contents.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (uuid, task) = parse_line(line)
+                    .with_context(|| format!("{}:{}", file_path.display(), index + 1))?;
+                if file == PENDING {
+                    storage.data.working_set.push(Some(uuid));
+                }
+                storage.data.order.push(uuid);
+                storage.data.tasks.insert(uuid, task.clone());
+                storage.saved.insert(uuid, (task, line.to_string()));
+            }
+        }
+        Ok(storage)
+    }
+
+    // Writes the data files and records the changed tasks in undo.data
+    fn save(&mut self) -> anyhow::Result<()> {
+        let mut undo = String::new();
+        let mut lines = HashMap::new();
+        let now = chrono::Utc::now().timestamp();
+        for uuid in &self.data.order {
+            let Some(task) = self.data.tasks.get(uuid) else {
+                continue;
+            };
+            let line = match self.saved.get(uuid) {
+                Some((saved, line)) if saved == task => line.clone(),
+                saved => {
+                    let line = compose_line(uuid, task);
+                    undo.push_str(&format!("time {now}\n"));
+                    if let Some((_, old)) = saved {
+                        undo.push_str(&format!("old {old}\n"));
+                    }
+                    undo.push_str(&format!("new {line}\n---\n"));
+                    line
+                }
+            };
+            lines.insert(*uuid, line);
+        }
+
+        // Pending tasks keep their line, and so their id, whatever taskchampion
+        // does with its working set. Completed ones move to completed.data.
+        let pending: Vec<&Uuid> = self
+            .data
+            .order
+            .iter()
+            .filter(|uuid| {
+                self.data
+                    .tasks
+                    .get(uuid)
+                    .is_some_and(|task| !is_completed(task))
+            })
+            .collect();
+        let completed: Vec<&Uuid> = self
+            .data
+            .order
+            .iter()
+            .filter(|uuid| self.data.tasks.get(uuid).is_some_and(is_completed))
+            .collect();
+        let contents = |uuids: Vec<&Uuid>| {
+            uuids
+                .into_iter()
+                .map(|uuid| format!("{}\n", lines[uuid]))
+                .collect::<String>()
+        };
+        write_if_changed(&self.path.join(PENDING), &contents(pending))?;
+        write_if_changed(&self.path.join(COMPLETED), &contents(completed))?;
+        if !undo.is_empty() {
+            let undo_path = self.path.join(UNDO);
+            let mut contents = fs::read_to_string(&undo_path).unwrap_or_default();
+            contents.push_str(&undo);
+            fs::write(&undo_path, contents)
+                .with_context(|| format!("Failed to write {}", undo_path.display()))?;
+        }
+
+        self.saved = self
+            .data
+            .tasks
+            .iter()
+            .map(|(uuid, task)| {
+                (
+                    *uuid,
+                    (task.clone(), lines.remove(uuid).unwrap_or_default()),
+                )
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+impl Storage for TaskDataStorage {
+    fn txn<'a>(&'a mut self) -> Result<Box<dyn StorageTxn + 'a>> {
+        Ok(Box::new(Txn {
+            storage: self,
+            new_data: None,
+        }))
+    }
+}
+
+// Changes are made to a copy of the data, which replaces it on commit
+struct Txn<'t> {
+    storage: &'t mut TaskDataStorage,
+    new_data: Option<Data>,
+}
+
+impl Txn<'_> {
+    fn data(&self) -> &Data {
+        self.new_data.as_ref().unwrap_or(&self.storage.data)
+    }
+
+    fn data_mut(&mut self) -> &mut Data {
+        self.new_data
+            .get_or_insert_with(|| self.storage.data.clone())
+    }
+}
+
+impl StorageTxn for Txn<'_> {
+    fn get_task(&mut self, uuid: Uuid) -> Result<Option<TaskMap>> {
+        Ok(self.data().tasks.get(&uuid).cloned())
+    }
+
+    fn get_pending_tasks(&mut self) -> Result<Vec<(Uuid, TaskMap)>> {
+        let data = self.data();
+        Ok(data
+            .working_set
+            .iter()
+            .flatten()
+            .filter_map(|uuid| data.tasks.get(uuid).map(|task| (*uuid, task.clone())))
+            .collect())
+    }
+
+    fn create_task(&mut self, uuid: Uuid) -> Result<bool> {
+        if self.data().tasks.contains_key(&uuid) {
+            return Ok(false);
+        }
+        let data = self.data_mut();
+        data.tasks.insert(uuid, TaskMap::new());
+        data.order.push(uuid);
+        Ok(true)
+    }
+
+    fn set_task(&mut self, uuid: Uuid, task: TaskMap) -> Result<()> {
+        let data = self.data_mut();
+        if data.tasks.insert(uuid, task).is_none() {
+            data.order.push(uuid);
+        }
+        Ok(())
+    }
+
+    fn delete_task(&mut self, uuid: Uuid) -> Result<bool> {
+        if !self.data().tasks.contains_key(&uuid) {
+            return Ok(false);
+        }
+        let data = self.data_mut();
+        data.tasks.remove(&uuid);
+        data.order.retain(|other| *other != uuid);
+        Ok(true)
+    }
+
+    fn all_tasks(&mut self) -> Result<Vec<(Uuid, TaskMap)>> {
+        Ok(self
+            .data()
+            .tasks
+            .iter()
+            .map(|(uuid, task)| (*uuid, task.clone()))
+            .collect())
+    }
+
+    fn all_task_uuids(&mut self) -> Result<Vec<Uuid>> {
+        Ok(self.data().tasks.keys().copied().collect())
+    }
+
+    // Taskwarrior 2.x never talks to a taskchampion sync server
+    fn base_version(&mut self) -> Result<Uuid> {
+        Ok(Uuid::nil())
+    }
+
+    fn set_base_version(&mut self, _version: Uuid) -> Result<()> {
+        Err(Error::Usage(String::from(
+            "Taskwarrior 2.x data can't be synced with a server",
+        )))
+    }
+
+    fn get_task_operations(&mut self, uuid: Uuid) -> Result<Vec<Operation>> {
+        Ok(self
+            .data()
+            .operations
+            .iter()
+            .filter(|op| op.get_uuid() == Some(uuid))
+            .cloned()
+            .collect())
+    }
+
+    fn unsynced_operations(&mut self) -> Result<Vec<Operation>> {
+        Ok(self.data().operations.clone())
+    }
+
+    fn num_unsynced_operations(&mut self) -> Result<usize> {
+        Ok(self.data().operations.len())
+    }
+
+    fn add_operation(&mut self, op: Operation) -> Result<()> {
+        self.data_mut().operations.push(op);
+        Ok(())
+    }
+
+    fn remove_operation(&mut self, op: Operation) -> Result<()> {
+        if self.data().operations.last() != Some(&op) {
+            return Err(Error::Database(String::from(
+                "Last operation does not match -- cannot remove",
+            )));
+        }
+        self.data_mut().operations.pop();
+        Ok(())
+    }
+
+    fn sync_complete(&mut self) -> Result<()> {
+        self.data_mut().operations.clear();
+        Ok(())
+    }
+
+    fn get_working_set(&mut self) -> Result<Vec<Option<Uuid>>> {
+        Ok(self.data().working_set.clone())
+    }
+
+    fn add_to_working_set(&mut self, uuid: Uuid) -> Result<usize> {
+        let working_set = &mut self.data_mut().working_set;
+        working_set.push(Some(uuid));
+        Ok(working_set.len())
+    }
+
+    fn set_working_set_item(&mut self, index: usize, uuid: Option<Uuid>) -> Result<()> {
+        let working_set = &mut self.data_mut().working_set;
+        let item = working_set
+            .get_mut(index)
+            .ok_or_else(|| Error::Database(format!("Index {index} is not in the working set")))?;
+        *item = uuid;
+        while working_set.len() > 1 && working_set.last() == Some(&None) {
+            working_set.pop();
+        }
+        Ok(())
+    }
+
+    fn clear_working_set(&mut self) -> Result<()> {
+        self.data_mut().working_set = vec![None];
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if let Some(data) = self.new_data.take() {
+            self.storage.data = data;
+            self.storage.save()?;
+        }
+        Ok(())
+    }
+}
+
+fn is_completed(task: &TaskMap) -> bool {
+    matches!(
+        task.get("status").map(String::as_str),
+        Some("completed" | "deleted")
+    )
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> anyhow::Result<()> {
+    if fs::read_to_string(path).unwrap_or_default() == contents {
+        return Ok(());
+    }
+    // Write next to the file first so a failed write can't lose any tasks
+    let temp = path.with_extension("data.tmp");
+    fs::write(&temp, contents).with_context(|| format!("Failed to write {}", temp.display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Reads a task written like [description:"Buy milk" status:"pending" uuid:"..."].
+// Tags and dependencies are lists there, taskchampion keeps one key for each.
+fn parse_line(line: &str) -> anyhow::Result<(Uuid, TaskMap)> {
+    let mut rest = line
+        .trim()
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(|| anyhow!("Not a taskwarrior task: {}", line))?
+        .trim_start();
+    let mut uuid = None;
+    let mut task = TaskMap::new();
+    while !rest.is_empty() {
+        let (name, value) = rest
+            .split_once(":\"")
+            .ok_or_else(|| anyhow!("Malformed attribute: {}", rest))?;
+        let end = value_end(value).ok_or_else(|| anyhow!("Unterminated value: {}", value))?;
+        let value = decode(&value[..end]);
+        rest = rest[name.len() + 2 + end + 1..].trim_start();
+        match name {
+            "uuid" => uuid = Some(Uuid::parse_str(&value)?),
+            "tags" => task.extend(list(&value).map(|tag| (format!("tag_{tag}"), String::new()))),
+            "depends" => task.extend(list(&value).map(|dep| (format!("dep_{dep}"), String::new()))),
+            _ => {
+                task.insert(name.to_string(), value);
+            }
+        }
+    }
+    let uuid = uuid.ok_or_else(|| anyhow!("Task without a uuid: {}", line))?;
+    Ok((uuid, task))
+}
+
+fn compose_line(uuid: &Uuid, task: &TaskMap) -> String {
+    let mut attributes: BTreeMap<&str, String> = BTreeMap::new();
+    let mut tags = Vec::new();
+    let mut depends = Vec::new();
+    for (name, value) in task {
+        if let Some(tag) = name.strip_prefix("tag_") {
+            tags.push(tag);
+        } else if let Some(dep) = name.strip_prefix("dep_") {
+            depends.push(dep);
+        } else {
+            attributes.insert(name, value.clone());
+        }
+    }
+    tags.sort();
+    depends.sort();
+    if !tags.is_empty() {
+        attributes.insert("tags", tags.join(","));
+    }
+    if !depends.is_empty() {
+        attributes.insert("depends", depends.join(","));
+    }
+    attributes.insert("uuid", uuid.to_string());
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|(name, value)| format!("{name}:\"{}\"", encode(value)))
+        .collect();
+    format!("[{}]", attributes.join(" "))
+}
+
+// Where a value ends, at the first quote that isn't escaped
+fn value_end(value: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Lists are comma separated, though some versions wrote dependencies as a
+// JSON array
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(|item| item.trim_matches(|c: char| c == '[' || c == ']' || c == '"' || c == ' '))
+        .filter(|item| !item.is_empty())
+}
+
+// Values are JSON escaped. Older versions replaced brackets and quotes with
+// entities instead.
+fn decode(value: &str) -> String {
+    let value = value
+        .replace("&open;", "[")
+        .replace("&close;", "]")
+        .replace("&dquot;", "\"");
+    let mut decoded = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some('r') => decoded.push('\r'),
+            Some('t') => decoded.push('\t'),
+            Some('b') => decoded.push('\u{8}'),
+            Some('f') => decoded.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+                decoded.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+            }
+            Some(other) => decoded.push(other),
+            None => decoded.push('\\'),
+        }
+    }
+    decoded
+}
+
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if c.is_control() => encoded.push_str(&format!("\\u{:04x}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use taskchampion::{Operations, Replica, Status};
+
+    const UUID: &str = "4e6ac96b-4d7c-4cbb-a4a4-63e1ed4fd4b3";
+
+    #[test]
+    fn test_lines() {
+        let line = format!(
+            "[annotation_1749513600:\"See [[Note]]\" depends:\"[\\\"{UUID}\\\"]\" description:\"Say \\\"hi\\\" &open;now&close;\" status:\"pending\" tags:\"home,next\" uuid:\"{UUID}\"]"
+        );
+        let (uuid, task) = parse_line(&line).unwrap();
+        assert_eq!(uuid.to_string(), UUID);
+        assert_eq!(task["description"], "Say \"hi\" [now]");
+        assert_eq!(task["annotation_1749513600"], "See [[Note]]");
+        assert!(task.contains_key("tag_home") && task.contains_key("tag_next"));
+        assert!(task.contains_key(&format!("dep_{UUID}")));
+
+        let composed = compose_line(&uuid, &task);
+        assert_eq!(
+            composed,
+            format!(
+                "[annotation_1749513600:\"See [[Note]]\" depends:\"{UUID}\" description:\"Say \\\"hi\\\" [now]\" status:\"pending\" tags:\"home,next\" uuid:\"{UUID}\"]"
+            )
+        );
+        assert_eq!(parse_line(&composed).unwrap(), (uuid, task));
+
+        assert!(parse_line("[description:\"No uuid\"]").is_err());
+        assert!(parse_line("description:\"Not a task\"").is_err());
+    }
+
+    #[test]
+    fn test_storage() {
+        let path = testdir::testdir!();
+        let completed = format!("[description:\"Done\" status:\"completed\" uuid:\"{UUID}\"]\n");
+        fs::write(path.join(COMPLETED), &completed).unwrap();
+        let mut replica = Replica::new(Box::new(TaskDataStorage::open(&path).unwrap()));
+        assert_eq!(replica.all_task_uuids().unwrap().len(), 1);
+
+        let uuid = Uuid::new_v4();
+        let mut ops = Operations::new();
+        let mut task = replica.create_task(uuid, &mut ops).unwrap();
+        task.set_description(String::from("Write docs"), &mut ops)
+            .unwrap();
+        task.set_status(Status::Pending, &mut ops).unwrap();
+        task.add_tag(&"docs".parse().unwrap(), &mut ops).unwrap();
+        replica.commit_operations(ops).unwrap();
+        assert_eq!(replica.working_set().unwrap().by_uuid(uuid), Some(1));
+
+        let pending = fs::read_to_string(path.join(PENDING)).unwrap();
+        assert!(pending.contains("description:\"Write docs\""));
+        assert!(pending.contains("tags:\"docs\""));
+        assert_eq!(fs::read_to_string(path.join(COMPLETED)).unwrap(), completed);
+        let undo = fs::read_to_string(path.join(UNDO)).unwrap();
+        assert!(undo.starts_with("time ") && undo.ends_with("---\n"));
+        assert!(!undo.contains("old "));
+
+        // Completing a task moves it to completed.data
+        let mut ops = Operations::new();
+        let mut task = replica.get_task(uuid).unwrap().unwrap();
+        task.set_status(Status::Completed, &mut ops).unwrap();
+        replica.commit_operations(ops).unwrap();
+        replica.rebuild_working_set(false).unwrap();
+        assert_eq!(fs::read_to_string(path.join(PENDING)).unwrap(), "");
+        let completed = fs::read_to_string(path.join(COMPLETED)).unwrap();
+        assert_eq!(completed.lines().count(), 2);
+        assert!(
+            fs::read_to_string(path.join(UNDO))
+                .unwrap()
+                .contains("old ")
+        );
+
+        // A fresh storage reads everything back
+        let mut replica = Replica::new(Box::new(TaskDataStorage::open(&path).unwrap()));
+        let task = replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(task.get_status(), Status::Completed);
+        assert!(task.has_tag(&"docs".parse().unwrap()));
+
+        assert!(TaskDataStorage::open(&path.join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_waiting_keeps_its_line() {
+        let path = testdir::testdir!();
+        let lines: Vec<String> = ["pending", "waiting", "pending"]
+            .iter()
+            .map(|status| {
+                format!(
+                    "[description:\"{status}\" status:\"{status}\" uuid:\"{}\"]",
+                    Uuid::new_v4()
+                )
+            })
+            .collect();
+        let pending = lines
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+        fs::write(path.join(PENDING), &pending).unwrap();
+        let mut replica = Replica::new(Box::new(TaskDataStorage::open(&path).unwrap()));
+
+        // Taskchampion leaves waiting tasks out of the working set
+        replica.rebuild_working_set(true).unwrap();
+        let uuid = Uuid::new_v4();
+        let mut ops = Operations::new();
+        let mut task = replica.create_task(uuid, &mut ops).unwrap();
+        task.set_status(Status::Pending, &mut ops).unwrap();
+        replica.commit_operations(ops).unwrap();
+
+        let written = fs::read_to_string(path.join(PENDING)).unwrap();
+        let written: Vec<&str> = written.lines().collect();
+        assert_eq!(written.len(), 4);
+        assert_eq!(
+            written[..3],
+            lines.iter().map(String::as_str).collect::<Vec<_>>()
+        );
+        assert!(written[3].contains(&uuid.to_string()));
+        let undo = fs::read_to_string(path.join(UNDO)).unwrap();
+        assert_eq!(undo.matches("---").count(), 1);
+    }
+}
@@ -1,13 +1,25 @@
 use anyhow::{Context, Result, anyhow};
-use chrono::NaiveDate;
 use paste::paste;
 use regex::Regex;
+use serde::Deserialize;
 use std::fmt::{self, Display};
 use std::iter::Peekable;
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::FromStr;
 use std::string::String;
+use std::sync::LazyLock;
 use taskchampion::{Task, Uuid};
 use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 
+use crate::attribute::{self, AttributeValue, Attributes};
+use crate::config::{
+    ActiveFormat, Expired, Mapping, Markdown, NestedTags, PriorityMapping, RequiredField, Settings,
+    TagPlacement, UntilFormat, UuidFormat,
+};
+use crate::datetime::{self, TaskDate};
+use crate::note::NoteDefaults;
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Status {
     Pending,
@@ -17,7 +29,7 @@ pub enum Status {
 
 impl PartialEq<taskchampion::Status> for Status {
     fn eq(&self, other: &taskchampion::Status) -> bool {
-        return (*self == Status::Pending && *other == taskchampion::Status::Pending)
+        return (*self == Status::Pending && is_pending(other))
             || (*self == Status::Complete && *other == taskchampion::Status::Completed)
             || (*self == Status::Canceled && *other == taskchampion::Status::Deleted);
     }
@@ -25,9 +37,20 @@ impl PartialEq<taskchampion::Status> for Status {
 
 impl PartialEq<Status> for taskchampion::Status {
     fn eq(&self, other: &Status) -> bool {
-        return (*other == Status::Pending && *self == taskchampion::Status::Pending)
-            || (*other == Status::Complete && *self == taskchampion::Status::Completed)
-            || (*other == Status::Canceled && *self == taskchampion::Status::Deleted);
+        other == self
+    }
+}
+
+// Taskwarrior 2 gave waiting tasks a status of their own, which taskchampion
+// reads as unknown. They are pending tasks with a wait date, whether or not
+// that date has passed.
+const WAITING: &str = "waiting";
+
+pub fn is_pending(status: &taskchampion::Status) -> bool {
+    match status {
+        taskchampion::Status::Pending => true,
+        taskchampion::Status::Unknown(status) => status == WAITING,
+        _ => false,
     }
 }
 
@@ -57,18 +80,73 @@ impl Display for Status {
     }
 }
 
-impl From<taskchampion::Status> for Status {
-    fn from(tc_status: taskchampion::Status) -> Self {
+// The list marker in front of the checkbox
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ListMarker {
+    // -, * or +
+    Bullet(char),
+    // The number and delimiter of an ordered list item, e.g. 1. or 2)
+    Ordered(u32, char),
+}
+
+impl Default for ListMarker {
+    fn default() -> Self {
+        ListMarker::Bullet('-')
+    }
+}
+
+impl Display for ListMarker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListMarker::Bullet(bullet) => write!(f, "{bullet}"),
+            ListMarker::Ordered(number, delimiter) => write!(f, "{number}{delimiter}"),
+        }
+    }
+}
+
+impl FromStr for ListMarker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "-" | "*" | "+" => Ok(ListMarker::Bullet(s.chars().next().unwrap())),
+            _ => {
+                let delimiter = s
+                    .chars()
+                    .next_back()
+                    .filter(|c| *c == '.' || *c == ')')
+                    .ok_or_else(|| anyhow!("Unknown list marker: {}", s))?;
+                let number = s[..s.len() - 1]
+                    .parse()
+                    .with_context(|| format!("Unknown list marker: {}", s))?;
+                Ok(ListMarker::Ordered(number, delimiter))
+            }
+        }
+    }
+}
+
+// Recurring templates and statuses this tool doesn't know have no checkbox
+impl TryFrom<taskchampion::Status> for Status {
+    type Error = anyhow::Error;
+
+    fn try_from(tc_status: taskchampion::Status) -> Result<Self> {
         match tc_status {
-            taskchampion::Status::Pending => return Status::Pending,
-            taskchampion::Status::Completed => return Status::Complete,
-            taskchampion::Status::Deleted => return Status::Canceled,
-            _ => return Status::Pending,
+            taskchampion::Status::Pending => Ok(Status::Pending),
+            taskchampion::Status::Completed => Ok(Status::Complete),
+            taskchampion::Status::Deleted => Ok(Status::Canceled),
+            taskchampion::Status::Unknown(status) if status == WAITING => Ok(Status::Pending),
+            taskchampion::Status::Recurring => {
+                Err(anyhow!("Recurring templates aren't synced with markdown"))
+            }
+            taskchampion::Status::Unknown(status) => {
+                Err(anyhow!("Unknown task status: {}", status))
+            }
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum Priority {
     Lowest,
     Low,
@@ -84,6 +162,36 @@ impl Default for Priority {
     }
 }
 
+impl Priority {
+    // Name used when storing the exact priority in taskchampion
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Lowest => "lowest",
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Highest => "highest",
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "lowest" => Ok(Priority::Lowest),
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "medium" => Ok(Priority::Medium),
+            "high" => Ok(Priority::High),
+            "highest" => Ok(Priority::Highest),
+            _ => Err(anyhow!("Unknown priority: {}", s)),
+        }
+    }
+}
+
 impl Display for Priority {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -97,162 +205,222 @@ impl Display for Priority {
     }
 }
 
-const SIGNIFICANT_EMOJI: &[&str] = &[
+pub const SIGNIFICANT_EMOJI: &[&str] = &[
     &"📅", &"⏳", &"🛫", &"➕", &"✅", &"❌", &"🔺", &"⏫", &"🔼", &"🔽", &"⏬", &"🔁", &"🆔",
-    &"⛔", &"🔨",
+    &"⛔", &"🔨", "▶️", "▶", "⏱️", "⏱", "⌛", "⌛️", "⏬️",
 ];
 
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
 pub struct ObsidianTask {
     pub uuid: Option<Uuid>,
+    // The id written in the markdown when it is a prefix of the uuid
+    pub short_id: Option<String>,
+    // The ^block-id at the end of the line, without the caret
+    pub block_id: Option<String>,
+    pub marker: ListMarker,
     pub status: Status,
+    // Started in taskwarrior and not stopped yet
+    pub active: bool,
+    // When the task was started, if the markdown says
+    pub started: Option<TaskDate>,
+    // Time spent on the task according to taskwarrior, never synced back
+    pub tracked: Option<chrono::Duration>,
     pub description: String,
     pub tags: Vec<String>,
-    pub due: Option<NaiveDate>,
-    pub scheduled: Option<NaiveDate>,
-    pub start: Option<NaiveDate>,
-    pub created: Option<NaiveDate>,
-    pub done: Option<NaiveDate>,
-    pub canceled: Option<NaiveDate>,
+    pub due: Option<TaskDate>,
+    pub scheduled: Option<TaskDate>,
+    pub start: Option<TaskDate>,
+    pub created: Option<TaskDate>,
+    pub done: Option<TaskDate>,
+    pub canceled: Option<TaskDate>,
+    // When taskwarrior expires the task
+    pub until: Option<TaskDate>,
+    // Values of the UDAs declared in the settings
+    pub attributes: Attributes,
     pub priority: Priority,
     pub project: Option<String>,
+    // Project the task gets from its note when it has no explicit project
+    pub inferred_project: Option<String>,
+    // Defaults from the note the task is in
+    pub defaults: Rc<NoteDefaults>,
     pub tz: chrono_tz::Tz,
+    pub settings: Rc<Settings>,
 }
 
-const MIDNIGHT: chrono::NaiveTime =
-    chrono::NaiveTime::from_hms_opt(0, 0, 0).expect("Invalid datetime");
-
 macro_rules! parse_date {
-    ($tc:expr, $tcData:expr) => {
-        $tc.get_value($tcData).map(|date: &str| {
-            let date_int = date.parse::<i64>().expect("Could not parse timestamp");
-            chrono::DateTime::from_timestamp(date_int, 0)
-                .expect("Invalid timestamp")
-                .date_naive()
-        })
+    ($tc:expr, $tcData:expr, $tz:expr) => {
+        $tc.get_value($tcData)
+            .map(|date: &str| TaskDate::from_timestamp(date, $tz))
     };
 }
 
-impl From<taskchampion::Task> for ObsidianTask {
-    fn from(tc: taskchampion::Task) -> Self {
-        // Massage the tags into the right format
-        // If we have the 'next' tag, this will affect
-        // our priority distinction below so we have to
-        // do this first
-        let mut is_next_pri = false;
-        let tags: Vec<String> = tc
+impl ObsidianTask {
+    // Builds the markdown representation of a taskchampion task, with all
+    // dates expressed in the given timezone and fields read from the attributes
+    // named in the mapping settings
+    pub fn from_tc(tc: taskchampion::Task, tz: &chrono_tz::Tz, settings: &Rc<Settings>) -> Self {
+        let mapping = &settings.mapping;
+
+        // Massage the tags into the right format, leaving out the tag that
+        // only marks the highest priority
+        let priority = read_priority(&tc, &mapping.priority);
+        let tc_tags: Vec<String> = tc
             .get_tags()
             .filter_map(|tag| {
                 if tag.is_user() {
-                    let tag_string = tag.to_string().replace("tag_", "");
-                    if tag_string == "next" {
-                        is_next_pri = true;
-                    }
-                    return Some(tag_string);
+                    return Some(tag.to_string().replace("tag_", ""));
                 }
                 None
             })
+            .filter(|tag| !(priority == Priority::Highest && *tag == mapping.priority.highest_tag))
+            .collect();
+        let tags = mapping
+            .tags
+            .decode(&tc_tags, tc.get_value(&mapping.tags.attribute));
+        let inline_tags = parse_tags(&tc.get_description().to_string());
+        let missing_tags: Vec<String> = tags
+            .iter()
+            .filter(|tag| !inline_tags.contains(tag))
+            .cloned()
             .collect();
+        // Only pending tasks can be worked on
+        let active = mapping.tracks_active() && tc.is_active() && is_pending(&tc.get_status());
+        let started = parse_date!(tc, "start", tz).filter(|_| active);
 
-        ObsidianTaskBuilder::new()
+        let mut task = ObsidianTaskBuilder::new()
+            .tz(*tz)
+            .settings(settings.clone())
             .uuid(tc.get_uuid())
-            .status(tc.get_status().into())
-            .priority(match tc.get_priority() {
-                "L" => Priority::Low,
-                "H" => {
-                    if is_next_pri {
-                        Priority::Highest
-                    } else {
-                        Priority::High
-                    }
-                }
-                _ => Priority::Normal,
-            })
+            // Tasks that can't be synced are skipped before they get here
+            .status(tc.get_status().try_into().unwrap_or_default())
+            .priority(priority)
+            // Obsidian only supports inline tags, so the tags have to be placed back
+            // into the description, unless an older version left them there
+            .description(add_tags(
+                tc.get_description(),
+                &missing_tags,
+                &settings.markdown.tag_placement,
+            ))
             .tags(&tags)
-            // Obsidian only supports inline tags, so if we added tags in TC we have to just
-            // append them into the description
-            .description(
-                String::from(tc.get_description())
-                    + &tags
-                        .iter()
-                        .map(|tag| format!(" #{tag}"))
-                        .collect::<String>()
-                        .as_str(),
-            )
-            .due(parse_date!(tc, "due"))
-            .scheduled(parse_date!(tc, "scheduled"))
-            .start(parse_date!(tc, "wait"))
-            .created(parse_date!(tc, "created"))
+            .due(parse_date!(tc, &mapping.due, tz))
+            .scheduled(parse_date!(tc, &mapping.scheduled, tz))
+            .start(parse_date!(tc, &mapping.start, tz))
+            .created(parse_date!(tc, &mapping.created, tz))
             .done(match tc.get_status() {
-                taskchampion::Status::Completed => parse_date!(tc, "end"),
+                taskchampion::Status::Completed => parse_date!(tc, &mapping.done, tz),
                 _ => None,
             })
             .canceled(match tc.get_status() {
-                taskchampion::Status::Deleted => parse_date!(tc, "end"),
+                taskchampion::Status::Deleted => parse_date!(tc, &mapping.canceled, tz),
                 _ => None,
             })
-            .project(tc.get_value("project"))
-            .build()
+            .until(parse_date!(tc, &mapping.until, tz))
+            .attributes(read_attributes(&tc, settings, tz))
+            .project(tc.get_value(&mapping.project))
+            .active(active, started)
+            .build();
+
+        // Taskwarrior deletes expired tasks itself, but not until it next runs
+        if is_expired(&tc, mapping) {
+            match settings.markdown.expired {
+                Expired::Cancel => {
+                    task.status = Status::Canceled;
+                    task.canceled = task.until;
+                    task.active = false;
+                    task.started = None;
+                }
+                Expired::Tag if !task.tags.contains(&settings.markdown.expired_tag) => {
+                    let tag = settings.markdown.expired_tag.clone();
+                    task.description = add_tags(
+                        &task.description,
+                        std::slice::from_ref(&tag),
+                        &settings.markdown.tag_placement,
+                    );
+                    task.tags.push(tag);
+                }
+                _ => {}
+            }
+        }
+        task
     }
 }
 
-macro_rules! convert_dates {
-    ($tz:expr, $($member:expr),*) => {
-        $(
-        $member = $member.map(|value| {
-            value
-                .and_time(MIDNIGHT)
-                .and_local_timezone(*$tz)
-                .earliest()
-                .unwrap()
-                .date_naive()
-        });
-        )*
-    };
+// Reads the declared UDAs of a taskchampion task, skipping values that don't
+// fit their type
+fn read_attributes(tc: &taskchampion::Task, settings: &Settings, tz: &chrono_tz::Tz) -> Attributes {
+    settings
+        .uda
+        .iter()
+        .filter_map(|(name, uda)| {
+            let value = AttributeValue::from_tc(tc.get_value(name)?, uda.kind, tz).ok()?;
+            Some((name.clone(), value))
+        })
+        .collect()
+}
+
+// Pending tasks past their until date, which taskwarrior deletes when it next
+// runs
+fn is_expired(tc: &taskchampion::Task, mapping: &Mapping) -> bool {
+    is_pending(&tc.get_status())
+        && tc
+            .get_value(&mapping.until)
+            .and_then(|until| until.parse::<i64>().ok())
+            .is_some_and(|until| until <= chrono::Utc::now().timestamp())
 }
 
+// Reads the priority of a taskchampion task through the priority mapping
+fn read_priority(tc: &taskchampion::Task, mapping: &PriorityMapping) -> Priority {
+    let value = tc.get_value(&mapping.attribute).unwrap_or("");
+    let has_highest_tag = !mapping.highest_tag.is_empty()
+        && tc
+            .get_tags()
+            .filter(|tag| tag.is_user())
+            .any(|tag| tag.to_string() == mapping.highest_tag);
+    let exact = Some(mapping.exact_attribute.as_str())
+        .filter(|attribute| !attribute.is_empty())
+        .and_then(|attribute| tc.get_value(attribute));
+    mapping.priority(value, has_highest_tag, exact)
+}
+
+// Dates are compared as instants so that a time written in markdown matches
+// taskchampion regardless of how it is formatted
 macro_rules! compare_date_fn {
-    ($name:ident, $taskParam:tt, $tcData:tt) => {
+    ($name:ident, $taskParam:tt) => {
         pub fn $name(&self, other: &taskchampion::Task) -> bool {
-            let task_date = self.$taskParam.map(|ts| {
-                ts.and_time(MIDNIGHT)
-                    .and_local_timezone(self.tz)
-                    .unwrap()
-                    .date_naive()
-            });
-            let tc_date = other.get_value($tcData).map(|val| {
-                chrono::DateTime::from_timestamp(val.parse::<i64>().expect("Invalid timestamp"), 0)
-                    .expect("Invalid timestamp")
-                    .date_naive()
-            });
+            let task_date = self.$taskParam.map(|date| date.to_utc(&self.tz));
+            let tc_date = other
+                .get_value(&self.settings.mapping.$taskParam)
+                .map(|val| TaskDate::from_timestamp(val, &self.tz).to_utc(&self.tz));
             task_date == tc_date
         }
     };
 }
 
 impl ObsidianTask {
-    pub fn with_tz(mut self, tz: &chrono_tz::Tz) -> Self {
-        self.tz = tz.clone();
-
-        convert_dates!(
-            tz,
-            self.due,
-            self.scheduled,
-            self.start,
-            self.created,
-            self.done,
-            self.canceled
-        );
-
-        self
+    compare_date_fn!(compare_due, due);
+    compare_date_fn!(compare_schedule, scheduled);
+    compare_date_fn!(compare_start, start);
+    compare_date_fn!(compare_created, created);
+    compare_date_fn!(compare_done, done);
+    compare_date_fn!(compare_canceled, canceled);
+    compare_date_fn!(compare_until, until);
+
+    pub fn compare_attributes(&self, other: &taskchampion::Task) -> bool {
+        self.attributes == read_attributes(other, &self.settings, &self.tz)
     }
 
-    compare_date_fn!(compare_due, due, "due");
-    compare_date_fn!(compare_schedule, scheduled, "scheduled");
-    compare_date_fn!(compare_start, start, "wait");
-    compare_date_fn!(compare_created, created, "created");
-    compare_date_fn!(compare_done, done, "end");
-    compare_date_fn!(compare_canceled, canceled, "end");
+    // True when the task has expired in taskchampion but the markdown doesn't
+    // show it yet
+    pub fn compare_expired(&self, other: &taskchampion::Task) -> bool {
+        if !is_expired(other, &self.settings.mapping) {
+            return true;
+        }
+        match self.settings.markdown.expired {
+            Expired::Cancel => self.status == Status::Canceled,
+            Expired::Tag => self.tags.contains(&self.settings.markdown.expired_tag),
+            Expired::Keep => true,
+        }
+    }
 
     pub fn compare_uuid(&self, other: &taskchampion::Task) -> bool {
         match self.uuid {
@@ -265,22 +433,75 @@ impl ObsidianTask {
         self.status == other.get_status()
     }
 
+    // The start time is only compared when the markdown shows one, and only as
+    // precisely as it is written
+    pub fn compare_active(&self, other: &taskchampion::Task) -> bool {
+        if !self.settings.mapping.tracks_active() {
+            return true;
+        }
+        let tc_active = other.is_active() && is_pending(&other.get_status());
+        if self.active != tc_active {
+            return false;
+        }
+        let time_format = self.settings.markdown.time_format.as_str();
+        match (self.started, other.get_value("start")) {
+            (Some(started), Some(tc_started)) => {
+                started.format(time_format)
+                    == TaskDate::from_timestamp(tc_started, &self.tz).format(time_format)
+            }
+            _ => true,
+        }
+    }
+
+    // Taskchampion holds the description without its inline tags, though tasks
+    // synced by older versions still have them there
     pub fn compare_description(&self, other: &taskchampion::Task) -> bool {
-        self.description == other.get_description()
+        strip_tags(&self.description) == strip_tags(other.get_description())
+    }
+
+    // Rewrites the description for a new set of tags, keeping the tags that remain
+    // where they were written and placing new ones according to the settings
+    pub fn retag_description(&self, tags: &[String]) -> String {
+        let description = remove_tags(&self.description, |tag| !tags.iter().any(|t| t == tag));
+        let added: Vec<String> = tags
+            .iter()
+            .filter(|tag| !self.tags.contains(tag))
+            .cloned()
+            .collect();
+        add_tags(&description, &added, &self.settings.markdown.tag_placement)
+    }
+
+    // True when the tag is only present in taskchampion to mark the highest priority
+    pub fn is_priority_tag(&self, tag: &str) -> bool {
+        let highest_tag = &self.settings.mapping.priority.highest_tag;
+        self.effective_priority() == Priority::Highest
+            && !highest_tag.is_empty()
+            && tag == highest_tag
+            && !self.effective_tags().contains(highest_tag)
     }
 
     pub fn compare_tags(&self, other: &taskchampion::Task) -> bool {
+        let mapping = &self.settings.mapping.tags;
+        let effective_tags = self.effective_tags();
+        if mapping.nested == NestedTags::Attribute
+            && mapping.stored(&effective_tags).as_deref() != other.get_value(&mapping.attribute)
+        {
+            return false;
+        }
+
         let tc_tags: Vec<String> = other
             .get_tags()
             .filter(|itm| itm.is_user())
             .map(|itm| itm.to_string())
+            .filter(|itm| !self.is_priority_tag(itm))
             .collect();
-        if self.tags.len() != tc_tags.len() {
+        let tags = mapping.encode(&effective_tags);
+        if tags.len() != tc_tags.len() {
             return false;
         }
 
-        for tag in &self.tags {
-            if !tc_tags.contains(&tag) {
+        for tag in &tags {
+            if !tc_tags.contains(tag) {
                 return false;
             }
         }
@@ -289,59 +510,175 @@ impl ObsidianTask {
     }
 
     pub fn compare_priority(&self, other: &taskchampion::Task) -> bool {
-        if self.priority == Priority::Highest {
-            return other
-                .get_tags()
-                .filter(|itm| itm.is_user())
-                .map(|itm| itm.to_string())
-                .collect::<Vec<String>>()
-                .contains(&String::from("next"));
-        }
-        let tc_priority = other.get_value("priority").unwrap_or("");
-        match self.priority {
-            Priority::Lowest | Priority::Low => return tc_priority == "L",
-            Priority::Normal => return tc_priority == "",
-            Priority::Medium => return tc_priority == "M",
-            Priority::High | Priority::Highest => return tc_priority == "H",
+        self.effective_priority() == read_priority(other, &self.settings.mapping.priority)
+    }
+
+    // True when the task passes the tag and metadata rules of the filter settings
+    pub fn passes_filter(&self) -> bool {
+        let filter = &self.settings.filter;
+        if let Some(required) = &filter.tag {
+            let required = required.trim_start_matches('#');
+            let nested = format!("{required}/");
+            if !self
+                .effective_tags()
+                .iter()
+                .any(|tag| tag == required || tag.starts_with(&nested))
+            {
+                return false;
+            }
+        }
+
+        filter.require.iter().all(|field| match field {
+            RequiredField::Due => self.due.is_some(),
+            RequiredField::Scheduled => self.scheduled.is_some(),
+            RequiredField::Start => self.start.is_some(),
+            RequiredField::Date => {
+                self.due.is_some() || self.scheduled.is_some() || self.start.is_some()
+            }
+            RequiredField::Priority => self.effective_priority() != Priority::Normal,
+            RequiredField::Project => self.effective_project().is_some(),
+        })
+    }
+
+    // The tags written on the task along with any from the note defaults
+    pub fn effective_tags(&self) -> Vec<String> {
+        let mut tags = self.tags.clone();
+        for tag in &self.defaults.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags
+    }
+
+    // Tasks without a priority of their own take the note default
+    pub fn effective_priority(&self) -> Priority {
+        match (&self.priority, &self.defaults.priority) {
+            (Priority::Normal, Some(priority)) => priority.clone(),
+            (priority, _) => priority.clone(),
+        }
+    }
+
+    // Carries the note context of the markdown task over to a task read from
+    // taskchampion, leaving out whatever the note defaults provide
+    pub fn inherit_defaults(&mut self, md_task: &ObsidianTask) {
+        self.inferred_project = md_task.inferred_project.clone();
+        self.defaults = md_task.defaults.clone();
+
+        let implied = |tag: &str| {
+            self.defaults.tags.iter().any(|itm| itm == tag)
+                && !md_task.tags.iter().any(|itm| itm == tag)
         };
+        self.description = remove_tags(&self.description, implied);
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .filter(|tag| !implied(tag))
+            .cloned()
+            .collect();
+        self.tags = tags;
+
+        if md_task.priority == Priority::Normal
+            && self.defaults.priority == Some(self.priority.clone())
+        {
+            self.priority = Priority::Normal;
+        }
     }
 
     pub fn compare_project(&self, other: &taskchampion::Task) -> bool {
-        let tc_project = other.get_value("project");
-        self.project == tc_project.map(|prj| prj.to_string())
+        let tc_project = other.get_value(&self.settings.mapping.project);
+        self.effective_project() == tc_project
+    }
+
+    // An explicit project always wins over the one inferred from the note
+    pub fn effective_project(&self) -> Option<&str> {
+        self.project.as_deref().or(self.inferred_project.as_deref())
     }
 }
 
 impl Display for ObsidianTask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time_format = self.settings.markdown.time_format.as_str();
         let mut task = String::new();
-        task.push_str(&format!("- {} {}", self.status, self.description));
-        if let Some(project) = &self.project {
+        let global_filter = &self.settings.markdown.global_filter;
+        let active_format = self.settings.markdown.active_format;
+        let status = match active_format {
+            ActiveFormat::Status if self.active => {
+                format!("[{}]", self.settings.markdown.active_status)
+            }
+            _ => self.status.to_string(),
+        };
+        if global_filter.is_empty() {
+            task.push_str(&format!("{} {} {}", self.marker, status, self.description));
+        } else {
+            task.push_str(&format!(
+                "{} {} {} {}",
+                self.marker, status, global_filter, self.description
+            ));
+        }
+        if let Some(project) = &self.project
+            && self.inferred_project.as_ref() != Some(project)
+        {
             task.push_str(&format!(" 🔨 {}", project));
         }
         if let Some(due) = self.due {
-            task.push_str(&format!(" 📅 {}", due.format("%Y-%m-%d")));
+            task.push_str(&format!(" 📅 {}", due.format(time_format)));
         }
         if let Some(scheduled) = self.scheduled {
-            task.push_str(&format!(" ⏳ {}", scheduled.format("%Y-%m-%d")));
+            task.push_str(&format!(" ⏳ {}", scheduled.format(time_format)));
         }
         if let Some(start) = self.start {
-            task.push_str(&format!(" 🛫 {}", start.format("%Y-%m-%d")));
+            task.push_str(&format!(" 🛫 {}", start.format(time_format)));
         }
         if let Some(created) = self.created {
-            task.push_str(&format!(" ➕ {}", created.format("%Y-%m-%d")));
+            task.push_str(&format!(" ➕ {}", created.format(time_format)));
         }
         if let Some(done) = self.done {
-            task.push_str(&format!(" ✅ {}", done.format("%Y-%m-%d")));
+            task.push_str(&format!(" ✅ {}", done.format(time_format)));
         }
         if let Some(canceled) = self.canceled {
-            task.push_str(&format!(" ❌ {}", canceled.format("%Y-%m-%d")));
+            task.push_str(&format!(" ❌ {}", canceled.format(time_format)));
+        }
+        if let Some(until) = self.until {
+            match self.settings.markdown.until_format {
+                UntilFormat::Emoji => task.push_str(&format!(" ⌛ {}", until.format(time_format))),
+                UntilFormat::Dataview => {
+                    task.push_str(&format!(" [until:: {}]", until.format(time_format)))
+                }
+            }
+        }
+        if self.active && active_format == ActiveFormat::Marker {
+            match self.started {
+                Some(started) => task.push_str(&format!(" ▶️ {}", started.format(time_format))),
+                None => task.push_str(" ▶️"),
+            }
+        }
+        if let Some(tracked) = self.tracked {
+            task.push_str(&format!(" ⏱ {}", datetime::format_duration(tracked)));
+        }
+        for (name, value) in &self.attributes {
+            task.push_str(&format!(
+                " {}",
+                attribute::format_attribute(name, value, &self.settings)
+            ));
         }
         if self.priority != Priority::Normal {
             task.push_str(&format!(" {}", self.priority.to_string()));
         }
-        if let Some(uuid) = self.uuid {
-            task.push_str(&format!(" [[uuid: {}|⚔️]]", uuid));
+        // 🆔 only ever holds the full uuid, as the Tasks plugin writes short ids too
+        let short_id = self
+            .short_id
+            .clone()
+            .filter(|_| self.settings.markdown.uuid_format != UuidFormat::Id);
+        if let Some(id) = short_id.or(self.uuid.map(|uuid| uuid.to_string())) {
+            task.push_str(&format!(
+                " {}",
+                uuid_marker(&id, &self.settings.markdown.uuid_format)
+            ));
+        }
+        // Obsidian only recognises block ids at the very end of the line
+        if let Some(block_id) = &self.block_id {
+            task.push_str(&format!(" ^{block_id}"));
         }
 
         write!(f, "{task}")
@@ -357,14 +694,14 @@ macro_rules! define_date_functions {
         $(
             paste! {
                 #[allow(dead_code)]
-                pub fn $field(mut self, value: Option<NaiveDate>) -> Self {
+                pub fn $field(mut self, value: Option<TaskDate>) -> Self {
                     self.task.$field = value;
                     self
                 }
 
                 #[allow(dead_code)]
                 pub fn [<$field _str>]<S: AsRef<str>>(mut self, value: S) -> Self {
-                    let dt = chrono::NaiveDate::parse_from_str(value.as_ref(), "%Y-%m-%d").unwrap();
+                    let dt = value.as_ref().parse::<TaskDate>().unwrap();
                     self.task.$field = Some(dt);
                     self
                 }
@@ -385,6 +722,12 @@ impl ObsidianTaskBuilder {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn settings(mut self, settings: Rc<Settings>) -> Self {
+        self.task.settings = settings;
+        self
+    }
+
     pub fn uuid(mut self, uuid: Uuid) -> Self {
         self.task.uuid = Some(uuid);
         self
@@ -395,6 +738,12 @@ impl ObsidianTaskBuilder {
         self
     }
 
+    pub fn active(mut self, active: bool, started: Option<TaskDate>) -> Self {
+        self.task.active = active;
+        self.task.started = started;
+        self
+    }
+
     pub fn description<T: Into<String>>(mut self, desc: T) -> Self {
         self.task.description = desc.into();
         self
@@ -423,7 +772,49 @@ impl ObsidianTaskBuilder {
         self
     }
 
-    define_date_functions!(due, scheduled, start, created, done, canceled);
+    #[allow(dead_code)]
+    pub fn short_id<T: Into<String>>(mut self, short_id: T) -> Self {
+        self.task.short_id = Some(short_id.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn attributes(mut self, attributes: Attributes) -> Self {
+        self.task.attributes = attributes;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn tracked(mut self, tracked: Option<chrono::Duration>) -> Self {
+        self.task.tracked = tracked;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn block_id<T: Into<String>>(mut self, block_id: T) -> Self {
+        self.task.block_id = Some(block_id.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn marker(mut self, marker: ListMarker) -> Self {
+        self.task.marker = marker;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn defaults(mut self, defaults: Rc<NoteDefaults>) -> Self {
+        self.task.defaults = defaults;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn inferred_project(mut self, project: Option<impl Into<String>>) -> Self {
+        self.task.inferred_project = project.map(|x| x.into());
+        self
+    }
+
+    define_date_functions!(due, scheduled, start, created, done, canceled, until);
 
     pub fn build(self) -> ObsidianTask {
         self.task
@@ -438,8 +829,12 @@ impl PartialEq<Task> for ObsidianTask {
             && self.compare_created(other)
             && self.compare_done(other)
             && self.compare_canceled(other)
+            && self.compare_until(other)
+            && self.compare_expired(other)
+            && self.compare_attributes(other)
             && self.compare_uuid(other)
             && self.compare_status(other)
+            && self.compare_active(other)
             && self.compare_description(other)
             && self.compare_tags(other)
             && self.compare_priority(other)
@@ -449,42 +844,58 @@ impl PartialEq<Task> for ObsidianTask {
 
 #[derive(Debug, PartialEq)]
 enum ObsidianMetadata {
-    Due(NaiveDate),
-    Scheduled(NaiveDate),
-    Start(NaiveDate),
-    Created(NaiveDate),
-    Done(NaiveDate),
-    Canceled(NaiveDate),
+    Due(TaskDate),
+    Scheduled(TaskDate),
+    Start(TaskDate),
+    Created(TaskDate),
+    Done(TaskDate),
+    Canceled(TaskDate),
+    Until(TaskDate),
     Priority(Priority),
     Project(String),
+    Active(Option<TaskDate>),
+    Tracked(chrono::Duration),
+    // Text that couldn't be read, which stays in the description
+    Unreadable(String),
 }
 
 struct MetadataParser<'a> {
     metadata: Peekable<Graphemes<'a>>,
+    settings: &'a Settings,
 }
 
-impl MetadataParser<'_> {
-    fn new(input: &'_ String) -> MetadataParser<'_> {
+impl<'a> MetadataParser<'a> {
+    fn new(input: &'a String, settings: &'a Settings) -> MetadataParser<'a> {
         MetadataParser {
             metadata: input.graphemes(true).peekable(),
+            settings,
         }
     }
 }
 
 macro_rules! process_date {
-    ($parser:ident, $variant:path) => {
-        let mut date: String = $parser.metadata.by_ref().take(11).collect();
-        date = date.trim().to_string();
-        let nd = NaiveDate::parse_from_str(&date, "%Y-%m-%d");
-        if let Err(err) = nd {
-            return Some(Err(anyhow!(
-                "Failed to parse date: {} with error: {}",
-                date,
-                err
-            )));
-        }
-
-        return Some(Ok($variant(nd.unwrap())));
+    ($parser:ident, $variant:expr) => {
+        let remaining: String = $parser.metadata.clone().collect();
+        let date = TaskDate::parse_prefix(&remaining, &$parser.settings.markdown.time_format);
+        let (date, consumed) = match date {
+            Ok(date) => date,
+            Err(err) => {
+                // Skip over the malformed date so it isn't mistaken for other metadata
+                $parser.metadata.by_ref().take(11).for_each(drop);
+                return Some(Err(err));
+            }
+        };
+
+        // Advance past the graphemes that made up the date and time
+        let mut advanced = 0;
+        while advanced < consumed {
+            match $parser.metadata.next() {
+                Some(grapheme) => advanced += grapheme.len(),
+                None => break,
+            }
+        }
+
+        return Some(Ok($variant(date)));
     };
 }
 
@@ -514,11 +925,46 @@ impl Iterator for MetadataParser<'_> {
                 "❌" => {
                     process_date!(self, ObsidianMetadata::Canceled);
                 }
+                "⌛" | "⌛️" => {
+                    process_date!(self, ObsidianMetadata::Until);
+                }
                 "🔺" => return Some(Ok(ObsidianMetadata::Priority(Priority::Highest))),
                 "⏫" => return Some(Ok(ObsidianMetadata::Priority(Priority::High))),
                 "🔼" => return Some(Ok(ObsidianMetadata::Priority(Priority::Medium))),
                 "🔽" => return Some(Ok(ObsidianMetadata::Priority(Priority::Low))),
-                "⏬️" => return Some(Ok(ObsidianMetadata::Priority(Priority::Lowest))),
+                "⏬" | "⏬️" => return Some(Ok(ObsidianMetadata::Priority(Priority::Lowest))),
+                "▶️" | "▶" => {
+                    // The start time is optional
+                    let remaining: String = self.metadata.clone().collect();
+                    if TaskDate::parse_prefix(&remaining, &self.settings.markdown.time_format)
+                        .is_err()
+                    {
+                        return Some(Ok(ObsidianMetadata::Active(None)));
+                    }
+                    process_date!(self, |date| ObsidianMetadata::Active(Some(date)));
+                }
+                "⏱️" | "⏱" => {
+                    let remaining: String = self.metadata.clone().collect();
+                    let Some((duration, consumed)) = datetime::parse_duration_prefix(&remaining)
+                    else {
+                        let mut text = grapheme.to_string();
+                        while let Some(item) = self.metadata.peek() {
+                            if SIGNIFICANT_EMOJI.contains(item) {
+                                break;
+                            }
+                            text.push_str(self.metadata.next().unwrap());
+                        }
+                        return Some(Ok(ObsidianMetadata::Unreadable(text.trim().to_string())));
+                    };
+                    let mut advanced = 0;
+                    while advanced < consumed {
+                        match self.metadata.next() {
+                            Some(grapheme) => advanced += grapheme.len(),
+                            None => break,
+                        }
+                    }
+                    return Some(Ok(ObsidianMetadata::Tracked(duration)));
+                }
                 "🔨" => {
                     let mut project = String::new();
                     while let Some(item) = iter.peek() {
@@ -536,16 +982,74 @@ impl Iterator for MetadataParser<'_> {
     }
 }
 
-pub fn parse(mut task_string: String, tz: &chrono_tz::Tz) -> Option<ObsidianTask> {
+pub fn parse(
+    mut task_string: String,
+    tz: &chrono_tz::Tz,
+    settings: &Rc<Settings>,
+) -> Option<ObsidianTask> {
     let mut task = ObsidianTask::default();
 
     task.tz = tz.clone();
+    task.settings = settings.clone();
+
+    let (marker, status, active) = parse_preamble(&mut task_string, settings)?;
+    task.marker = marker;
+    task.status = status;
+    task.active = active;
 
-    let status = parse_preamble(&mut task_string);
-    task.status = status?;
+    if let Some(caps) = BLOCK_ID_RE.captures(&task_string) {
+        task.block_id = Some(caps["id"].to_string());
+        let start = caps.get(0).unwrap().start();
+        task_string.truncate(start);
+    }
 
-    let (metadata, uuid) = extract_task_parts(&mut task_string);
-    task.uuid = uuid.and_then(|id| id.ok());
+    // Dataview fields can sit anywhere in the line, so they are taken out
+    // before the description is split from the metadata
+    if let Some(caps) = UNTIL_FIELD_RE.captures(&task_string) {
+        let whole = caps.get(0).unwrap().range();
+        let time_format = settings.markdown.time_format.as_str();
+        if let Ok((date, consumed)) = TaskDate::parse_prefix(&caps["date"], time_format)
+            && caps["date"][consumed..].trim().is_empty()
+        {
+            task.until = Some(date);
+            task_string = format!(
+                "{} {}",
+                task_string[..whole.start].trim_end(),
+                task_string[whole.end..].trim_start()
+            )
+            .trim()
+            .to_string();
+        }
+    }
+
+    let (mut metadata, id) = extract_task_parts(&mut task_string);
+    task.attributes = attribute::take_attributes(&mut task_string, settings);
+    if let Some(metadata) = metadata.as_mut() {
+        task.attributes
+            .extend(attribute::take_attributes(metadata, settings));
+    }
+    if let Some(id) = id {
+        match Uuid::parse_str(&id) {
+            Ok(uuid) => task.uuid = Some(uuid),
+            // Short ids are resolved to the full uuid through the replica
+            Err(_) if is_short_id(&id) => task.short_id = Some(id.to_lowercase()),
+            Err(_) => {}
+        }
+    }
+
+    // The global filter only marks the line as a task, it isn't a tag
+    let global_filter = &settings.markdown.global_filter;
+    if !global_filter.is_empty()
+        && let Some(start) = find_global_filter(&task_string, global_filter)
+    {
+        task_string = format!(
+            "{} {}",
+            task_string[..start].trim_end(),
+            task_string[start + global_filter.len()..].trim_start()
+        )
+        .trim()
+        .to_string();
+    }
     let tags = parse_tags(&task_string);
     task.tags = tags;
 
@@ -555,7 +1059,7 @@ pub fn parse(mut task_string: String, tz: &chrono_tz::Tz) -> Option<ObsidianTask
     task.description = task_string;
 
     if let Some(metadata_str) = metadata {
-        let md = MetadataParser::new(&metadata_str);
+        let md = MetadataParser::new(&metadata_str, settings);
         for data in md.filter_map(Result::ok) {
             match data {
                 ObsidianMetadata::Due(date) => task.due = Some(date),
@@ -563,9 +1067,18 @@ pub fn parse(mut task_string: String, tz: &chrono_tz::Tz) -> Option<ObsidianTask
                 ObsidianMetadata::Start(date) => task.start = Some(date),
                 ObsidianMetadata::Created(date) => task.created = Some(date),
                 ObsidianMetadata::Canceled(date) => task.canceled = Some(date),
+                ObsidianMetadata::Until(date) => task.until = Some(date),
                 ObsidianMetadata::Scheduled(date) => task.scheduled = Some(date),
                 ObsidianMetadata::Priority(pri) => task.priority = pri,
                 ObsidianMetadata::Project(prj) => task.project = Some(prj),
+                ObsidianMetadata::Tracked(duration) => task.tracked = Some(duration),
+                ObsidianMetadata::Unreadable(text) => {
+                    task.description = format!("{} {text}", task.description);
+                }
+                ObsidianMetadata::Active(started) => {
+                    task.active = task.status == Status::Pending;
+                    task.started = started.filter(|_| task.active);
+                }
             }
         }
     }
@@ -573,18 +1086,105 @@ pub fn parse(mut task_string: String, tz: &chrono_tz::Tz) -> Option<ObsidianTask
     Some(task)
 }
 
-fn extract_task_parts(task: &mut String) -> (Option<String>, Option<Result<Uuid>>) {
-    // Returns a tuple with the metadata string and UUID as options
-    let mut uuid: Option<Result<Uuid>> = None;
-    let uuid_re = Regex::new(r"(?<whole>\[\[uuid: (?<uuid>.*)\|⚔️\u{FE0F}?\]\])").unwrap();
-    if let Some(caps) = uuid_re.captures(task) {
-        uuid = caps.name("uuid").map(|id| {
-            Uuid::parse_str(id.as_str())
-                .with_context(|| format!("Failed to parse UUID: {}", id.as_str()))
-        });
-        if let Some(whole) = caps.name("whole") {
-            *task = task.replace(whole.as_str(), "").trim().to_string();
-        }
+// True when the line should be synced under the global filter setting
+pub fn has_global_filter(line: &str, settings: &Settings) -> bool {
+    let global_filter = &settings.markdown.global_filter;
+    global_filter.is_empty() || find_global_filter(line, global_filter).is_some()
+}
+
+// Finds the global filter as a whole word, so #task doesn't match #tasks
+fn find_global_filter(line: &str, global_filter: &str) -> Option<usize> {
+    line.match_indices(global_filter)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let before = line[..start].chars().next_back();
+            let after = line[start + global_filter.len()..].chars().next();
+            before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+        })
+}
+
+// Matches the uuid marker in any of the formats only sharptask writes
+static UUID_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"\[\[uuid: (?<link>[^|\]]*)\|⚔️\u{FE0F}?\]\]",
+        r"|%%\s*tw:(?<comment>[^%\s]*)\s*%%",
+        r"|<!--\s*tw:(?<html>\S*?)\s*-->",
+    ))
+    .unwrap()
+});
+
+// The id format, which the Tasks plugin also uses for its own task ids. Only
+// a full uuid counts, short ids need one of the markers above.
+static ID_MARKER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"🆔\s*(?<id>[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})",
+    )
+    .unwrap()
+});
+
+// A Dataview until field, e.g. [until:: 2025-06-10]
+static UNTIL_FIELD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[until::\s*(?<date>[^\]]*)\]").unwrap());
+
+// A block reference id ending the line, e.g. ^tw-a80c42ce
+static BLOCK_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s\^(?<id>[A-Za-z0-9-]+)\s*$").unwrap());
+
+// Writes a uuid, or a short id standing in for one, in the given format
+pub fn uuid_marker(id: &str, format: &UuidFormat) -> String {
+    match format {
+        UuidFormat::Link => format!("[[uuid: {id}|⚔️]]"),
+        UuidFormat::Comment => format!("%%tw:{id}%%"),
+        UuidFormat::Html => format!("<!-- tw:{id} -->"),
+        UuidFormat::Id => format!("🆔 {id}"),
+    }
+}
+
+// A short id is the start of the uuid without its hyphens
+pub fn is_short_id(id: &str) -> bool {
+    (4..=32).contains(&id.len()) && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Rewrites the uuid marker of a line in another format. Returns None when the
+// line has no marker or it is already in that format.
+pub fn replace_uuid_marker(line: &str, format: &UuidFormat) -> Option<String> {
+    let (whole, id) = find_uuid_marker(line)?;
+    let is_uuid = Uuid::parse_str(id).is_ok();
+    if !is_uuid && (!is_short_id(id) || *format == UuidFormat::Id) {
+        return None;
+    }
+    let marker = uuid_marker(id, format);
+    if marker == line[whole.clone()] {
+        return None;
+    }
+    Some(format!(
+        "{}{}{}",
+        &line[..whole.start],
+        marker,
+        &line[whole.end..]
+    ))
+}
+
+// Finds the uuid marker of a line along with the id in it. The other formats
+// win over 🆔 so a Tasks plugin id never takes the place of a real marker.
+fn find_uuid_marker(line: &str) -> Option<(Range<usize>, &str)> {
+    let caps = UUID_RE
+        .captures(line)
+        .or_else(|| ID_MARKER_RE.captures(line))?;
+    let id = ["link", "comment", "html", "id"]
+        .iter()
+        .find_map(|name| caps.name(name))?;
+    Some((caps.get(0).unwrap().range(), id.as_str()))
+}
+
+fn extract_task_parts(task: &mut String) -> (Option<String>, Option<String>) {
+    // Returns a tuple with the metadata string and the id in the uuid marker
+    let mut uuid: Option<String> = None;
+    if let Some((whole, id)) = find_uuid_marker(task) {
+        uuid = Some(id.to_string());
+        *task = format!("{} {}", task[..whole.start].trim_end(), &task[whole.end..])
+            .trim()
+            .to_string();
     }
 
     // Capture up to first significant emoji, this is our task description with tags
@@ -604,34 +1204,118 @@ fn extract_task_parts(task: &mut String) -> (Option<String>, Option<Result<Uuid>
     (metadata, uuid)
 }
 
-fn parse_preamble(task_string: &mut String) -> Option<Status> {
+// Any CommonMark list marker followed by a checkbox, e.g. - [ ], * [x] or 1. [-]
+static PREAMBLE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(?<marker>[-*+]|\d{1,9}[.)]) \[(?<status>[^\]])\] (?<remaining>.*)").unwrap()
+});
+
+fn parse_preamble(
+    task_string: &mut String,
+    settings: &Settings,
+) -> Option<(ListMarker, Status, bool)> {
     // Remove the preamble: - [ ]
-    let preamble_re = Regex::new(r"\s*- \[(?<status>[x\- ])\] (?<remaining>.*)").unwrap();
-    let caps = preamble_re.captures(task_string)?;
-    let status = match caps.name("status")?.as_str() {
-        "x" => Status::Complete,
-        "-" => Status::Canceled,
-        " " => Status::Pending,
+    let caps = PREAMBLE_RE.captures(task_string)?;
+    let marker = caps["marker"].parse().ok()?;
+    let (status, active) = match caps["status"].chars().next()? {
+        'x' => (Status::Complete, false),
+        '-' => (Status::Canceled, false),
+        ' ' => (Status::Pending, false),
+        c if c == settings.markdown.active_status => (Status::Pending, true),
         _ => return None,
     };
     *task_string = caps.name("remaining")?.as_str().to_owned();
-    Some(status)
+    Some((marker, status, active))
 }
 
+// True when the checkbox holds one of the statuses sharptask syncs
+pub fn is_task_status(status: char, settings: &Markdown) -> bool {
+    matches!(status, 'x' | '-' | ' ') || status == settings.active_status
+}
+
+// Matches an inline tag along with what comes before it. Tags start after
+// whitespace or an opening parenthesis and can't be entirely numeric.
+pub static TAG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?<pre>^|\s|\()#(?<tag>[\p{L}\p{N}_/\-]*[\p{L}_/\-][\p{L}\p{N}_/\-]*)").unwrap()
+});
+static EMPTY_GROUP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s*\(\s*\)").unwrap());
+static TAG_GROUP_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((?:#[^\s()]+\s*)+\)$").unwrap());
+
+// Nested tags are kept whole, e.g. #area/work is the single tag area/work
 fn parse_tags(task_string: &String) -> Vec<String> {
-    let mut tags = Vec::new();
-    let mut graphemes = task_string.graphemes(true);
-    while let Some(grapheme) = graphemes.next() {
-        if grapheme == "#" {
-            let some_tags: String = graphemes.clone().take_while(|item| *item != " ").collect();
-            for a_tag in some_tags.split(|tag| tag == '/') {
-                tags.push(a_tag.to_string());
-            }
+    let mut tags: Vec<String> = Vec::new();
+    for caps in TAG_RE.captures_iter(task_string) {
+        let tag = caps["tag"].to_string();
+        if !tags.contains(&tag) {
+            tags.push(tag);
         }
     }
     tags
 }
 
+// Removes the inline tags from a description, leaving the text stored in taskchampion
+pub fn strip_tags(description: &str) -> String {
+    remove_tags(description, |_| true)
+}
+
+fn remove_tags<F: Fn(&str) -> bool>(description: &str, remove: F) -> String {
+    let mut result = String::with_capacity(description.len());
+    let mut last = 0;
+    for caps in TAG_RE.captures_iter(description) {
+        if !remove(&caps["tag"]) {
+            continue;
+        }
+        // The space before this tag may already have been skipped below
+        let whole = caps.get(0).unwrap();
+        if whole.start() >= last {
+            result.push_str(&description[last..whole.start()]);
+        }
+        last = whole.end();
+
+        // Keep the opening parenthesis of a tag group, without the space that
+        // separated this tag from the next
+        if &caps["pre"] == "(" {
+            result.push('(');
+            let rest = &description[last..];
+            last += rest.len() - rest.trim_start().len();
+        }
+    }
+    result.push_str(&description[last..]);
+
+    // Drop any tag group left empty
+    EMPTY_GROUP_RE.replace_all(&result, "").trim().to_string()
+}
+
+// Places tags into a description, either at the end or inside a trailing group
+fn add_tags(description: &str, tags: &[String], placement: &TagPlacement) -> String {
+    if tags.is_empty() {
+        return description.to_string();
+    }
+    let tag_str = tags
+        .iter()
+        .map(|tag| format!("#{tag}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+    if description.is_empty() {
+        return match placement {
+            TagPlacement::End => tag_str,
+            TagPlacement::Parentheses => format!("({tag_str})"),
+        };
+    }
+
+    match placement {
+        TagPlacement::End => format!("{description} {tag_str}"),
+        TagPlacement::Parentheses => {
+            // Add to an existing group of tags at the end of the line
+            if TAG_GROUP_RE.is_match(description) {
+                let open = &description[..description.len() - 1];
+                return format!("{open} {tag_str})");
+            }
+            format!("{description} ({tag_str})")
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -687,6 +1371,44 @@ mod tests {
                         .build(),
                 ),
             ),
+            (
+                "- [ ] Task with comment uuid 📅 2025-05-10 %%tw:a80c42ce-dd29-4dc7-8582-34f36fcf8b80%%",
+                Some(
+                    ObsidianTaskBuilder::new()
+                        .uuid(Uuid::parse_str("a80c42ce-dd29-4dc7-8582-34f36fcf8b80").unwrap())
+                        .description("Task with comment uuid")
+                        .due_str("2025-05-10")
+                        .build(),
+                ),
+            ),
+            (
+                "- [ ] Task with html uuid <!-- tw:a80c42ce-dd29-4dc7-8582-34f36fcf8b80 -->",
+                Some(
+                    ObsidianTaskBuilder::new()
+                        .uuid(Uuid::parse_str("a80c42ce-dd29-4dc7-8582-34f36fcf8b80").unwrap())
+                        .description("Task with html uuid")
+                        .build(),
+                ),
+            ),
+            (
+                "- [ ] Task with id uuid 🆔 a80c42ce-dd29-4dc7-8582-34f36fcf8b80 ⏫",
+                Some(
+                    ObsidianTaskBuilder::new()
+                        .uuid(Uuid::parse_str("a80c42ce-dd29-4dc7-8582-34f36fcf8b80").unwrap())
+                        .description("Task with id uuid")
+                        .priority(Priority::High)
+                        .build(),
+                ),
+            ),
+            (
+                "- [ ] Task with short id %%tw:A80C42CE%%",
+                Some(
+                    ObsidianTaskBuilder::new()
+                        .short_id("a80c42ce")
+                        .description("Task with short id")
+                        .build(),
+                ),
+            ),
             (
                 "- [ ] Task with invalid uuid [[uuid: uh-oh|⚔️]]",
                 Some(
@@ -700,7 +1422,7 @@ mod tests {
                 Some(
                     ObsidianTaskBuilder::new()
                         .description("Task with #some/tags")
-                        .tags(&["some", "tags"])
+                        .tags(&["some/tags"])
                         .build(),
                 ),
             ),
@@ -729,11 +1451,21 @@ mod tests {
                         .build(),
                 ),
             ),
+            (
+                "- [ ] Task with due time 📅 2025-06-13 14:00 ⏳ 2025-06-12",
+                Some(
+                    ObsidianTaskBuilder::new()
+                        .description("Task with due time")
+                        .due_str("2025-06-13 14:00")
+                        .scheduled_str("2025-06-12")
+                        .build(),
+                ),
+            ),
         ];
 
         for test in test_bank {
             let test_local = String::from(test.0);
-            let task = parse(test_local, &chrono_tz::UTC);
+            let task = parse(test_local, &chrono_tz::UTC, &Rc::default());
             assert_eq!(task, test.1);
         }
     }
@@ -745,14 +1477,12 @@ mod tests {
         );
         let (metadata, uuid) = extract_task_parts(&mut task);
         assert_eq!(metadata.clone().unwrap(), "🔺⏫🔼🔽⏬️");
-        assert_eq!(
-            uuid.unwrap().unwrap(),
-            Uuid::parse_str("96bb3816-aedd-4033-8ff6-4746a700aac8").unwrap()
-        );
+        assert_eq!(uuid.unwrap(), "96bb3816-aedd-4033-8ff6-4746a700aac8");
         assert_eq!(task, "Test task stuff");
 
         let metadata_str = metadata.clone().unwrap();
-        let mut metadata_iter = MetadataParser::new(&metadata_str);
+        let settings = Settings::default();
+        let mut metadata_iter = MetadataParser::new(&metadata_str, &settings);
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
             ObsidianMetadata::Priority(Priority::Highest)
@@ -785,40 +1515,38 @@ mod tests {
             metadata.clone().unwrap(),
             "📅 2025-05-19 ⏳ 2025-05-19 🛫 2025-05-19 ➕ 2025-05-19 ✅ 2025-05-19 ❌ 2025-05-19 🔨 This is a project 🔺⏫🔼🔽⏬️"
         );
-        assert_eq!(
-            uuid.unwrap().unwrap(),
-            Uuid::parse_str("96bb3816-aedd-4033-8ff6-4746a700aac8").unwrap()
-        );
+        assert_eq!(uuid.unwrap(), "96bb3816-aedd-4033-8ff6-4746a700aac8");
         assert_eq!(task, "Test #task stuff #project/tag");
 
         let metadata_str = metadata.clone().unwrap();
-        let mut metadata_iter = MetadataParser::new(&metadata_str);
+        let settings = Settings::default();
+        let mut metadata_iter = MetadataParser::new(&metadata_str, &settings);
         let reference = chrono_tz::America::Chicago
             .with_ymd_and_hms(2025, 5, 19, 0, 0, 0)
             .unwrap();
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Due(reference.date_naive())
+            ObsidianMetadata::Due(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Scheduled(reference.date_naive())
+            ObsidianMetadata::Scheduled(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Start(reference.date_naive())
+            ObsidianMetadata::Start(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Created(reference.date_naive())
+            ObsidianMetadata::Created(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Done(reference.date_naive())
+            ObsidianMetadata::Done(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
-            ObsidianMetadata::Canceled(reference.date_naive())
+            ObsidianMetadata::Canceled(reference.date_naive().into())
         );
         assert_eq!(
             metadata_iter.next().unwrap().unwrap(),
@@ -846,7 +1574,75 @@ mod tests {
         );
 
         let tags = parse_tags(&task);
-        assert_eq!(tags, ["task", "project", "tag"]);
+        assert_eq!(tags, ["task", "project/tag"]);
+    }
+
+    #[test]
+    fn test_time_format() {
+        let settings = Rc::new(Settings {
+            markdown: crate::config::Markdown {
+                time_format: String::from("%I:%M %p"),
+                ..crate::config::Markdown::default()
+            },
+            ..Settings::default()
+        });
+        let task = parse(
+            String::from("- [ ] Afternoon task 📅 2025-06-13 02:30 PM 🛫 2025-06-10"),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.due, Some("2025-06-13 14:30".parse().unwrap()));
+        assert_eq!(task.start, Some("2025-06-10".parse().unwrap()));
+        assert_eq!(
+            task.to_string(),
+            "- [ ] Afternoon task 📅 2025-06-13 02:30 PM 🛫 2025-06-10"
+        );
+    }
+
+    #[test]
+    fn test_tag_placement() {
+        assert_eq!(
+            strip_tags("Call #client about the #area/work report (#a #b)"),
+            "Call about the report"
+        );
+        assert_eq!(strip_tags("#todo Issue #42 in C#"), "Issue #42 in C#");
+        assert_eq!(parse_tags(&String::from("#todo Issue #42 in C#")), ["todo"]);
+
+        let tags = [String::from("new"), String::from("area/home")];
+        assert_eq!(
+            add_tags("Buy milk", &tags, &TagPlacement::End),
+            "Buy milk #new #area/home"
+        );
+        assert_eq!(
+            add_tags("Buy milk", &tags, &TagPlacement::Parentheses),
+            "Buy milk (#new #area/home)"
+        );
+        assert_eq!(
+            add_tags("Buy milk (#old)", &tags, &TagPlacement::Parentheses),
+            "Buy milk (#old #new #area/home)"
+        );
+
+        let task = parse(
+            String::from("- [ ] Call #client about the report (#old #other)"),
+            &chrono_tz::UTC,
+            &Rc::default(),
+        )
+        .unwrap();
+        let tags = [
+            String::from("client"),
+            String::from("other"),
+            String::from("new"),
+        ];
+        assert_eq!(
+            task.retag_description(&tags),
+            "Call #client about the report (#other) #new"
+        );
+        let tags = [String::from("other")];
+        assert_eq!(
+            task.retag_description(&tags),
+            "Call about the report (#other)"
+        );
     }
 
     #[test]
@@ -855,7 +1651,8 @@ mod tests {
             String::from("Test task stuff 📅25 [[uuid: 96bb3816-aedd-4033-8ff6-4746a700aac8|⚔️]]");
         let (metadata, _) = extract_task_parts(&mut task);
         let metadata_str = metadata.clone().unwrap();
-        let mut metadata_iter = MetadataParser::new(&metadata_str);
+        let settings = Settings::default();
+        let mut metadata_iter = MetadataParser::new(&metadata_str, &settings);
         assert!(metadata_iter.next().unwrap().is_err());
     }
 
@@ -909,4 +1706,280 @@ mod tests {
             "- [ ] Test 🔨 Test project 📅 2025-05-10 ⏫ [[uuid: 25287dfa-c5b5-4772-8788-d64a41abf352|⚔️]]"
         );
     }
+
+    #[test]
+    fn test_filter() {
+        let mut settings = Settings::default();
+        settings.filter.tag = Some(String::from("#task"));
+        settings.filter.require = vec![RequiredField::Date];
+        let settings = Rc::new(settings);
+
+        let task = |line: &str| parse(line.to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert!(task("- [ ] Test #task 📅 2025-05-10").passes_filter());
+        assert!(task("- [ ] Test #task/work ⏳ 2025-05-10").passes_filter());
+        assert!(!task("- [ ] Test #task").passes_filter());
+        assert!(!task("- [ ] Test #tasks 📅 2025-05-10").passes_filter());
+        assert!(!task("- [ ] Test 📅 2025-05-10").passes_filter());
+
+        // Tags from the note defaults count too
+        let mut defaulted = task("- [ ] Test 📅 2025-05-10");
+        defaulted.defaults = Rc::new(NoteDefaults {
+            tags: vec![String::from("task")],
+            ..NoteDefaults::default()
+        });
+        assert!(defaulted.passes_filter());
+    }
+
+    #[test]
+    fn test_global_filter() {
+        let mut settings = Settings::default();
+        settings.markdown.global_filter = String::from("#task");
+        let settings = Rc::new(settings);
+
+        let line = "- [ ] #task Buy milk #errand 📅 2025-05-10";
+        assert!(has_global_filter(line, &settings));
+        let task = parse(line.to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert_eq!(task.description, "Buy milk #errand");
+        assert_eq!(task.tags, ["errand"]);
+        assert_eq!(task.to_string(), line);
+
+        // The filter is moved to the front when it was written elsewhere
+        let task = parse(
+            "- [ ] Buy milk #task 📅 2025-05-10".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.description, "Buy milk");
+        assert!(task.tags.is_empty());
+        assert_eq!(task.to_string(), line.replace(" #errand", ""));
+
+        assert!(!has_global_filter("- [ ] Buy milk #tasks", &settings));
+        assert!(!has_global_filter("- [ ] Buy milk", &settings));
+        assert!(has_global_filter("- [ ] Buy milk", &Settings::default()));
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let settings = Rc::new(Settings::default());
+        for (line, marker) in [
+            ("- [ ] Task", ListMarker::Bullet('-')),
+            ("* [x] Task", ListMarker::Bullet('*')),
+            ("+ [-] Task", ListMarker::Bullet('+')),
+            ("1. [ ] Task", ListMarker::Ordered(1, '.')),
+            ("12) [ ] Task", ListMarker::Ordered(12, ')')),
+        ] {
+            let task = parse(line.to_string(), &chrono_tz::UTC, &settings).unwrap();
+            assert_eq!(task.marker, marker);
+            assert_eq!(task.description, "Task");
+            assert_eq!(task.to_string(), line);
+        }
+
+        assert!(parse("-[ ] Task".to_string(), &chrono_tz::UTC, &settings).is_none());
+        assert!(parse("1 [ ] Task".to_string(), &chrono_tz::UTC, &settings).is_none());
+        assert!(parse("Text - [ ] Task".to_string(), &chrono_tz::UTC, &settings).is_none());
+    }
+
+    #[test]
+    fn test_tracked() {
+        let settings = Rc::new(Settings::default());
+        let task = parse(
+            "- [ ] Test ⏱ 1h30m 📅 2025-06-08".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.tracked, Some(chrono::Duration::minutes(90)));
+        assert_eq!(task.description, "Test");
+
+        // Text that isn't a duration is kept
+        let task = parse(
+            "- [ ] Test 📅 2025-06-08 ⏱ soon".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.tracked, None);
+        assert_eq!(task.due, Some("2025-06-08".parse().unwrap()));
+        assert_eq!(task.description, "Test ⏱ soon");
+        assert_eq!(task.to_string(), "- [ ] Test ⏱ soon 📅 2025-06-08");
+        let reparsed = parse(task.to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert_eq!(reparsed.to_string(), task.to_string());
+    }
+
+    #[test]
+    fn test_until() {
+        let settings = Rc::new(Settings::default());
+        let mut dataview = Settings::default();
+        dataview.markdown.until_format = UntilFormat::Dataview;
+        let dataview = Rc::new(dataview);
+        for line in [
+            "- [ ] Test 📅 2025-06-08 ⌛ 2025-06-10",
+            "- [ ] Test 📅 2025-06-08 ⌛️ 2025-06-10",
+            "- [ ] Test [until:: 2025-06-10] 📅 2025-06-08",
+            "- [ ] Test 📅 2025-06-08 [until:: 2025-06-10]",
+        ] {
+            let task = parse(line.to_string(), &chrono_tz::UTC, &settings).unwrap();
+            assert_eq!(task.description, "Test");
+            assert_eq!(task.until, Some("2025-06-10".parse().unwrap()));
+            assert_eq!(task.due, Some("2025-06-08".parse().unwrap()));
+            assert_eq!(task.to_string(), "- [ ] Test 📅 2025-06-08 ⌛ 2025-06-10");
+            let task = parse(line.to_string(), &chrono_tz::UTC, &dataview).unwrap();
+            assert_eq!(
+                task.to_string(),
+                "- [ ] Test 📅 2025-06-08 [until:: 2025-06-10]"
+            );
+        }
+
+        let task = parse(
+            "- [ ] Test [until:: someday]".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.until, None);
+        assert_eq!(task.description, "Test [until:: someday]");
+        assert_eq!(task.to_string(), "- [ ] Test [until:: someday]");
+    }
+
+    #[test]
+    fn test_active() {
+        let settings = Rc::new(Settings::default());
+        let task = parse("- [/] Test".to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert!(task.active);
+        assert_eq!(task.status, Status::Pending);
+        assert_eq!(task.to_string(), "- [/] Test");
+
+        // The marker is read whatever the format and written in the configured one
+        let task = parse(
+            "- [ ] Test ▶️ 2025-06-08 14:30".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert!(task.active);
+        assert_eq!(task.started.unwrap().format("%H:%M"), "2025-06-08 14:30");
+        assert_eq!(task.description, "Test");
+        assert_eq!(task.to_string(), "- [/] Test");
+
+        let mut marker_settings = Settings::default();
+        marker_settings.markdown.active_format = ActiveFormat::Marker;
+        marker_settings.markdown.active_status = 's';
+        let marker_settings = Rc::new(marker_settings);
+        for (line, started) in [
+            ("- [ ] Test 📅 2025-06-10 ▶️ 2025-06-08 14:30", true),
+            ("- [ ] Test ▶️", false),
+        ] {
+            let task = parse(line.to_string(), &chrono_tz::UTC, &marker_settings).unwrap();
+            assert!(task.active);
+            assert_eq!(task.started.is_some(), started);
+            assert_eq!(task.to_string(), line);
+        }
+        let task = parse("- [s] Test".to_string(), &chrono_tz::UTC, &marker_settings).unwrap();
+        assert!(task.active);
+        assert_eq!(task.to_string(), "- [ ] Test ▶️");
+
+        // Finished tasks aren't active, and other statuses aren't tasks
+        let task = parse(
+            "- [x] Test ▶️ 2025-06-08".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert!(!task.active);
+        assert!(parse("- [s] Test".to_string(), &chrono_tz::UTC, &settings).is_none());
+        assert!(parse("- [?] Test".to_string(), &chrono_tz::UTC, &settings).is_none());
+    }
+
+    #[test]
+    fn test_block_ids() {
+        let settings = Rc::new(Settings::default());
+        let line = "- [ ] Test #tag 📅 2025-05-10 [[uuid: a80c42ce-dd29-4dc7-8582-34f36fcf8b80|⚔️]] ^tw-a80c42ce";
+        let task = parse(line.to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert_eq!(task.block_id.as_deref(), Some("tw-a80c42ce"));
+        assert_eq!(task.description, "Test #tag");
+        assert!(task.uuid.is_some());
+        assert_eq!(task.to_string(), line);
+
+        // Block ids written by the user are kept as they are
+        let task = parse(
+            "- [ ] Test ^my-block".to_string(),
+            &chrono_tz::UTC,
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(task.block_id.as_deref(), Some("my-block"));
+        assert_eq!(task.description, "Test");
+
+        // A caret inside the description isn't a block id
+        let task = parse("- [ ] 2^10 ^ x".to_string(), &chrono_tz::UTC, &settings).unwrap();
+        assert_eq!(task.block_id, None);
+        assert_eq!(task.description, "2^10 ^ x");
+    }
+
+    #[test]
+    fn test_uuid_formats() {
+        let uuid = Uuid::parse_str("a80c42ce-dd29-4dc7-8582-34f36fcf8b80").unwrap();
+        let line = "- [ ] Test 📅 2025-05-10 [[uuid: a80c42ce-dd29-4dc7-8582-34f36fcf8b80|⚔️]]";
+        for format in [
+            UuidFormat::Link,
+            UuidFormat::Comment,
+            UuidFormat::Html,
+            UuidFormat::Id,
+        ] {
+            let mut settings = Settings::default();
+            settings.markdown.uuid_format = format;
+            let settings = Rc::new(settings);
+
+            let migrated = replace_uuid_marker(line, &format).unwrap_or(line.to_string());
+            let task = parse(migrated.clone(), &chrono_tz::UTC, &settings).unwrap();
+            assert_eq!(task.uuid, Some(uuid));
+            assert_eq!(task.to_string(), migrated);
+            assert!(replace_uuid_marker(&migrated, &format).is_none());
+        }
+
+        assert_eq!(
+            replace_uuid_marker(line, &UuidFormat::Comment).unwrap(),
+            "- [ ] Test 📅 2025-05-10 %%tw:a80c42ce-dd29-4dc7-8582-34f36fcf8b80%%"
+        );
+        assert!(replace_uuid_marker("- [ ] Test", &UuidFormat::Comment).is_none());
+
+        // A Tasks plugin id never takes the place of the real marker
+        let line = "- [ ] Foo 🆔 abc123 %%tw:a80c42ce-dd29-4dc7-8582-34f36fcf8b80%%";
+        let task = parse(
+            line.to_string(),
+            &chrono_tz::UTC,
+            &Rc::new(Settings::default()),
+        )
+        .unwrap();
+        assert_eq!(task.uuid, Some(uuid));
+        assert_eq!(task.short_id, None);
+        assert_eq!(task.description, "Foo");
+        assert_eq!(
+            replace_uuid_marker(line, &UuidFormat::Html).unwrap(),
+            "- [ ] Foo 🆔 abc123 <!-- tw:a80c42ce-dd29-4dc7-8582-34f36fcf8b80 -->"
+        );
+
+        // Short ids never come from 🆔, and are never written there
+        let task = parse(
+            String::from("- [ ] Foo 🆔 abc12345"),
+            &chrono_tz::UTC,
+            &Rc::new(Settings::default()),
+        )
+        .unwrap();
+        assert_eq!((task.uuid, task.short_id), (None, None));
+        let mut settings = Settings::default();
+        settings.markdown.uuid_format = UuidFormat::Id;
+        let task = ObsidianTaskBuilder::new()
+            .settings(Rc::new(settings))
+            .description("Foo")
+            .uuid(uuid)
+            .short_id("a80c42ce")
+            .build();
+        assert_eq!(
+            task.to_string(),
+            "- [ ] Foo 🆔 a80c42ce-dd29-4dc7-8582-34f36fcf8b80"
+        );
+        assert!(replace_uuid_marker("- [ ] Foo %%tw:a80c42ce%%", &UuidFormat::Id).is_none());
+    }
 }
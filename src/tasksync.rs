@@ -1,17 +1,36 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, NaiveTime, Utc};
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
 use colored::Colorize;
 use std::fs;
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use taskchampion::storage::AccessMode;
+use taskchampion::storage::{AccessMode, Storage};
 use taskchampion::{Replica, StorageConfig, Uuid};
 
+use crate::attribute::AttributeValue;
+use crate::config::{Backend, LinkFormat, NestedTags, UnlinkAction};
+use crate::datetime::TaskDate;
+use crate::taskdata::TaskDataStorage;
 use crate::taskparser::{self, ObsidianTask};
 
+const OBSIDIAN_URI: &str = "obsidian://";
+
 pub struct TaskWarriorSync {
     replica: Replica,
     tz: chrono_tz::Tz,
+    // Tasks found in a different note than the last sync
+    pub moves: Vec<TaskMove>,
+    // Whether this run's changes already start with an undo point
+    undo_point: bool,
+    // Every uuid in the replica, read once per run for the short ids
+    uuids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskMove {
+    pub description: String,
+    pub from: String,
+    pub to: String,
 }
 
 macro_rules! print_date_diff {
@@ -21,19 +40,8 @@ macro_rules! print_date_diff {
             "{}",
             format!(
                 "      {:?} -> {:?}",
-                $task.$taskMember.map(|val| val
-                    .and_time(MIDNIGHT)
-                    .and_local_timezone($task.tz)
-                    .earliest()
-                    .expect("Invalid timestamp")),
-                $tcTask.get_value($tcValue).map(|val| {
-                    chrono::DateTime::from_timestamp(
-                        val.parse::<i64>().expect("Invalid timestamp"),
-                        0,
-                    )
-                    .expect("Invalid timestamp")
-                    .with_timezone($tz)
-                })
+                $task.$taskMember.map(|val| val.to_utc(&$task.tz).with_timezone($tz)),
+                $tcTask.get_value($tcValue).map(|val| TaskDate::from_timestamp(val, $tz))
             )
             .yellow()
         );
@@ -41,26 +49,315 @@ macro_rules! print_date_diff {
     };
 }
 
-impl TaskWarriorSync {
-    pub fn new(path: &PathBuf, tz: &chrono_tz::Tz) -> Result<Self> {
-        let storage = StorageConfig::OnDisk {
-            taskdb_dir: path.clone(),
-            create_if_missing: false,
-            access_mode: AccessMode::ReadWrite,
+// Writes a date field into the attribute it is mapped to if it has changed
+macro_rules! update_date {
+    ($self:ident, $task:ident, $tcTask:ident, $ops:ident, $label:expr, $compare:ident, $field:ident) => {
+        if !$task.$compare(&$tcTask) {
+            let attribute = &$task.settings.mapping.$field;
+            println!(
+                "      {}",
+                format!(
+                    "{}: {:?} -> {:?}",
+                    $label,
+                    $tcTask
+                        .get_value(attribute)
+                        .map(|val| TaskDate::from_timestamp(val, &$self.tz)),
+                    $task.$field
+                )
+                .red()
+            );
+            $tcTask.set_value(
+                attribute,
+                $task.$field.map(|date| date.to_timestamp(&$self.tz)),
+                &mut $ops,
+            )?;
+        }
+    };
+}
+
+// Writes the task priority into its mapped attribute, adding or removing the
+// highest priority tag as needed
+fn set_priority(
+    task: &ObsidianTask,
+    tc_task: &mut taskchampion::Task,
+    ops: &mut taskchampion::Operations,
+) -> Result<()> {
+    let mapping = &task.settings.mapping.priority;
+    let priority = task.effective_priority();
+    let value = mapping.value(&priority);
+    let value = Some(value.to_string()).filter(|val| !val.is_empty());
+    tc_task.set_value(&mapping.attribute, value, ops)?;
+
+    // Normal priority needs no exact value, it's what an unset attribute means
+    if !mapping.exact_attribute.is_empty() {
+        let exact =
+            Some(priority.name().to_string()).filter(|_| priority != taskparser::Priority::Normal);
+        tc_task.set_value(&mapping.exact_attribute, exact, ops)?;
+    }
+
+    if !mapping.highest_tag.is_empty() {
+        let tag = format!("tag_{}", mapping.highest_tag);
+        if priority == taskparser::Priority::Highest {
+            tc_task.set_value(tag, Some(String::new()), ops)?;
+        } else if !task.effective_tags().contains(&mapping.highest_tag)
+            && tc_task.get_value(&tag).is_some()
+        {
+            tc_task.set_value(tag, None, ops)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes the declared UDAs, leaving values that only differ in how they are
+// written alone
+fn set_attributes(
+    task: &ObsidianTask,
+    tc_task: &mut taskchampion::Task,
+    tz: &chrono_tz::Tz,
+    ops: &mut taskchampion::Operations,
+) -> Result<()> {
+    for (name, uda) in &task.settings.uda {
+        let value = task.attributes.get(name);
+        let current = tc_task
+            .get_value(name)
+            .and_then(|current| AttributeValue::from_tc(current, uda.kind, tz).ok());
+        if value != current.as_ref() {
+            tc_task.set_value(name, value.map(|value| value.to_tc(tz)), ops)?;
+        }
+    }
+    Ok(())
+}
+
+// Starts or stops the taskchampion task, using the start time from the markdown
+// when it has one
+fn set_active(
+    task: &ObsidianTask,
+    tc_task: &mut taskchampion::Task,
+    tz: &chrono_tz::Tz,
+    ops: &mut taskchampion::Operations,
+) -> Result<()> {
+    match (task.active, task.started) {
+        (true, Some(started)) => tc_task.set_value("start", Some(started.to_timestamp(tz)), ops)?,
+        (true, None) => tc_task.start(ops)?,
+        (false, _) => tc_task.stop(ops)?,
+    }
+    Ok(())
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(value.parse().ok()?, 0)
+}
+
+// Pairs up the "Started task" and "Stopped task" annotations taskwarrior adds
+// when journal.time is on
+fn journal_time(tc_task: &taskchampion::Task, now: DateTime<Utc>) -> Duration {
+    let mut annotations: Vec<taskchampion::Annotation> = tc_task.get_annotations().collect();
+    annotations.sort_by_key(|annotation| annotation.entry);
+    let mut tracked = Duration::zero();
+    let mut start = None;
+    for annotation in annotations {
+        match annotation.description.as_str() {
+            "Started task" => start = start.or(Some(annotation.entry)),
+            "Stopped task" => {
+                if let Some(start) = start.take() {
+                    tracked += annotation.entry - start;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = start
+        && tc_task.is_active()
+    {
+        tracked += now - start;
+    }
+    tracked
+}
+
+// Where the task lives, relative to the vault when it is inside one
+fn source_file(file: &Path, vault_path: Option<&Path>) -> Option<String> {
+    if file.as_os_str().is_empty() {
+        return None;
+    }
+    let relative = vault_path
+        .and_then(|vault| file.strip_prefix(vault).ok())
+        .unwrap_or(file);
+    Some(relative.to_string_lossy().into_owned())
+}
+
+// The obsidian uri that opens the note the task is in, at the task line when
+// it has a block id
+fn obsidian_link(
+    file: &Path,
+    vault_path: Option<&Path>,
+    block_id: Option<&str>,
+    format: LinkFormat,
+) -> Option<String> {
+    let vault = vault_path?;
+    let vault_name = vault.file_name()?.to_string_lossy();
+    // Obsidian paths always use forward slashes, whatever the platform
+    let relative = file
+        .strip_prefix(vault)
+        .unwrap_or(Path::new(file.file_name()?));
+    let path = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let vault_name = encode_uri_component(&vault_name);
+    Some(match format {
+        LinkFormat::Open => {
+            // Leaving out the extension keeps links to notes at the root of
+            // the vault the same as before they were vault relative
+            let path = path.strip_suffix(".md").map_or(path.clone(), String::from);
+            let target = match block_id {
+                Some(block_id) => format!("{path}#^{block_id}"),
+                None => path,
+            };
+            format!(
+                "{OBSIDIAN_URI}open?vault={vault_name}&file={}",
+                encode_uri_component(&target)
+            )
         }
-        .into_storage()
-        .context("Failed to build storage context")?;
+        LinkFormat::AdvancedUri => {
+            let mut link = format!(
+                "{OBSIDIAN_URI}advanced-uri?vault={vault_name}&filepath={}",
+                encode_uri_component(&path)
+            );
+            if let Some(block_id) = block_id {
+                link.push_str(&format!("&block={}", encode_uri_component(block_id)));
+            }
+            link
+        }
+    })
+}
+
+// Percent encodes everything but the unreserved uri characters
+fn encode_uri_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+// The block id generated for a task, e.g. tw-a80c42ce
+fn block_id(task: &ObsidianTask, uuid: &Uuid) -> String {
+    match &task.short_id {
+        Some(short_id) => format!("tw-{short_id}"),
+        None => format!("tw-{}", &uuid.simple().to_string()[..8]),
+    }
+}
+
+// Replaces the taskchampion tags with the encoded obsidian tags, keeping the tag
+// that marks the highest priority
+fn set_tags(
+    task: &ObsidianTask,
+    tc_task: &mut taskchampion::Task,
+    ops: &mut taskchampion::Operations,
+) -> Result<()> {
+    let mapping = &task.settings.mapping.tags;
+    let tags = task.effective_tags();
+
+    // Clear out existing tags
+    for tag in tc_task
+        .get_tags()
+        .filter(|itm| itm.is_user() && !task.is_priority_tag(itm.as_ref()))
+        .collect::<Vec<taskchampion::Tag>>()
+    {
+        tc_task.set_value(format!("tag_{tag}"), None, ops)?;
+    }
+
+    // Add new tags
+    for tag in mapping.encode(&tags) {
+        tc_task.set_value(format!("tag_{tag}"), Some(String::new()), ops)?;
+    }
+
+    if mapping.nested == NestedTags::Attribute {
+        tc_task.set_value(&mapping.attribute, mapping.stored(&tags), ops)?;
+    }
+    Ok(())
+}
+
+impl TaskWarriorSync {
+    pub fn new(path: &PathBuf, backend: Backend, tz: &chrono_tz::Tz) -> Result<Self> {
+        // Everything else goes through the replica, whatever the storage
+        let storage: Box<dyn Storage> = match backend {
+            Backend::Taskchampion => StorageConfig::OnDisk {
+                taskdb_dir: path.clone(),
+                create_if_missing: false,
+                access_mode: AccessMode::ReadWrite,
+            }
+            .into_storage()
+            .context("Failed to build storage context")?,
+            Backend::Taskwarrior2 => Box::new(TaskDataStorage::open(path)?),
+        };
         Ok(TaskWarriorSync {
             replica: Replica::new(storage),
             tz: tz.clone(),
+            moves: Vec::new(),
+            undo_point: false,
+            uuids: None,
         })
     }
 
+    // Commits the operations, preceded by an undo point for the first change of
+    // the run so `task undo` reverts the whole run like any other command
+    fn commit(&mut self, mut ops: taskchampion::Operations) -> Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        if !self.undo_point {
+            ops.insert(0, taskchampion::Operation::UndoPoint);
+            self.undo_point = true;
+        }
+        self.replica
+            .commit_operations(ops)
+            .context("Failed to commit operations")
+    }
+
+    // Whether the run has changed any tasks
+    pub fn has_changes(&self) -> bool {
+        self.undo_point
+    }
+
+    // Drops completed and deleted tasks from the working set and gives new
+    // ones an id, as taskwarrior does after each command
+    pub fn rebuild_working_set(&mut self) -> Result<()> {
+        self.replica
+            .rebuild_working_set(false)
+            .context("Failed to rebuild the working set")
+    }
+
+    // Exchanges operations with the sync server, as `task sync` does
+    pub fn sync_server(&mut self, server: &mut Box<dyn taskchampion::Server>) -> Result<()> {
+        // The server may bring in new tasks
+        self.uuids = None;
+        self.replica
+            .sync(server, false)
+            .context("Failed to sync with the server")
+    }
+
+    fn task_uuids(&mut self) -> Result<&[Uuid]> {
+        if self.uuids.is_none() {
+            self.uuids = Some(self.replica.all_task_uuids()?);
+        }
+        Ok(self.uuids.as_deref().unwrap_or_default())
+    }
+
     #[cfg(test)]
     fn from_replica(replica: Replica, tz: &chrono_tz::Tz) -> Self {
         TaskWarriorSync {
             replica,
             tz: tz.clone(),
+            moves: Vec::new(),
+            undo_point: false,
+            uuids: None,
         }
     }
 
@@ -79,6 +376,22 @@ impl TaskWarriorSync {
     ) -> Result<bool> {
         // 1. If task has UUID, find it in TC DB
         let mut ops = taskchampion::Operations::new();
+        let settings = task.settings.clone();
+        let mapping = &settings.mapping;
+        let source = source_file(
+            file.as_ref(),
+            vault_path.as_ref().map(|vault| vault.as_ref()),
+        );
+        let link = |task: &ObsidianTask| {
+            obsidian_link(
+                file.as_ref(),
+                vault_path.as_ref().map(|vault| vault.as_ref()),
+                task.block_id.as_deref(),
+                settings.markdown.link_format,
+            )
+        };
+        // A new block id has to be written into the markdown
+        let mut new_block_id = false;
 
         match task.uuid {
             Some(_) => println!("  {}", format!("{}", task.to_string()).blue()),
@@ -87,10 +400,63 @@ impl TaskWarriorSync {
 
         if let Some(uuid) = task.uuid {
             if let Some(mut tc_task) = self.replica.get_task(uuid).ok().flatten() {
+                // Leave tasks alone rather than guessing a status for them
+                if let Err(err) = taskparser::Status::try_from(tc_task.get_status()) {
+                    println!("      {}", format!("{err:#}, skipping").yellow());
+                    return Ok(false);
+                }
+                if settings.markdown.block_ids && task.block_id.is_none() {
+                    task.block_id = Some(block_id(task, &uuid));
+                    new_block_id = true;
+                }
+
+                // Follow the task if it was moved to another note
+                if let Some(source) = &source
+                    && !mapping.source_file.is_empty()
+                {
+                    let previous = tc_task.get_value(&mapping.source_file).map(String::from);
+                    if previous.as_ref() != Some(source) {
+                        tc_task.set_value(&mapping.source_file, Some(source.clone()), &mut ops)?;
+                    }
+                    if let Some(from) = previous.filter(|from| from != source) {
+                        println!("      {}", format!("Moved: {from} -> {source}").red());
+                        self.moves.push(TaskMove {
+                            description: tc_task.get_description().to_string(),
+                            from,
+                            to: source.clone(),
+                        });
+                    }
+                }
+                if let Some(link) = &link(task) {
+                    let stale = tc_task.get_annotations().find(|annotation| {
+                        annotation.description.starts_with(OBSIDIAN_URI)
+                            && annotation.description != *link
+                    });
+                    if let Some(annotation) = stale {
+                        let key = format!("annotation_{}", annotation.entry.timestamp());
+                        tc_task.set_value(key, Some(link.clone()), &mut ops)?;
+                    }
+                }
+
+                // Older versions kept the inline tags in the stored description
+                let description = taskparser::strip_tags(&task.description);
+                if task.compare_description(&tc_task) && tc_task.get_description() != description {
+                    tc_task.set_description(description, &mut ops)?;
+                }
+
                 // If equal, skip processing
-                if *task == tc_task {
+                if *task == tc_task && ops.is_empty() {
                     println!("{}", "      No changes".yellow());
-                    return Ok(false);
+                    return Ok(new_block_id);
+                }
+
+                // Starting and stopping goes first, completing a task stops it
+                if !task.compare_active(&tc_task) {
+                    println!(
+                        "      {}",
+                        format!("Active: {} -> {}", tc_task.is_active(), task.active).red()
+                    );
+                    set_active(task, &mut tc_task, &self.tz, &mut ops)?;
                 }
 
                 // Status update
@@ -109,69 +475,16 @@ impl TaskWarriorSync {
                         format!(
                             "Desc: {} -> {}",
                             tc_task.get_description(),
-                            task.description
-                        )
-                        .red()
-                    );
-                    tc_task.set_description(task.description.clone(), &mut ops)?;
-                }
-
-                const MIDNIGHT: NaiveTime = chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap();
-
-                // Due date update
-                if !task.compare_due(&tc_task) {
-                    println!(
-                        "      {}",
-                        format!(
-                            "Due: {:?} -> {:?}",
-                            tc_task.get_due().map(|due| due.with_timezone(&self.tz)),
-                            task.due.map(|due| due
-                                .and_time(MIDNIGHT)
-                                .and_local_timezone(task.tz)
-                                .earliest())
-                        )
-                        .yellow()
-                    );
-                    tc_task.set_due(
-                        task.due.map(|date| {
-                            date.and_time(MIDNIGHT)
-                                .and_local_timezone(self.tz)
-                                .unwrap()
-                                .to_utc()
-                        }),
-                        &mut ops,
-                    )?;
-                }
-
-                // Wait date update
-                if !task.compare_start(&tc_task) {
-                    println!(
-                        "      {}",
-                        format!(
-                            "Wait: {:?} -> {:?}",
-                            tc_task.get_wait().map(|due| due.with_timezone(&self.tz)),
-                            task.start.map(|start| start
-                                .and_time(MIDNIGHT)
-                                .and_local_timezone(task.tz)
-                                .earliest())
+                            taskparser::strip_tags(&task.description)
                         )
                         .red()
                     );
-                    tc_task.set_wait(
-                        task.start.map(|date| {
-                            date.and_time(MIDNIGHT)
-                                .and_local_timezone(self.tz)
-                                .unwrap()
-                                .to_utc()
-                        }),
-                        &mut ops,
-                    )?;
+                    tc_task.set_description(taskparser::strip_tags(&task.description), &mut ops)?;
                 }
 
-                // Update priority
-                if !task.compare_priority(&tc_task) {
-                    tc_task.set_status(task.status.clone().into(), &mut ops)?;
-                }
+                // Due and start date updates
+                update_date!(self, task, tc_task, ops, "Due", compare_due, due);
+                update_date!(self, task, tc_task, ops, "Start", compare_start, start);
 
                 // Update tags
                 if !task.compare_tags(&tc_task) {
@@ -192,142 +505,68 @@ impl TaskWarriorSync {
                         )
                         .red()
                     );
-                    // Clear out existing tags
-                    for tag in tc_task
-                        .get_tags()
-                        .filter(|itm| itm.is_user())
-                        .collect::<Vec<taskchampion::Tag>>()
-                    {
-                        let tag_string = format!("tag_{tag}");
-                        tc_task.set_value(tag_string, None, &mut ops)?;
-                    }
-
-                    // Add new tags
-                    for tag in &task.tags {
-                        let tag_string = format!("tag_{tag}");
-                        tc_task.set_value(tag_string, Some(String::new()), &mut ops)?;
-                    }
+                    set_tags(task, &mut tc_task, &mut ops)?;
                 }
 
                 // Update end date
-                if task.status == taskparser::Status::Complete && !task.compare_done(&tc_task) {
-                    println!(
-                        "      {}",
-                        format!(
-                            "Complete Date: {:?} -> {:?}",
-                            tc_task.get_value("end").map(|val| DateTime::from_timestamp(
-                                val.parse().expect("Timestamp is not valid"),
-                                0
-                            )
-                            .expect("Timestamp is not valid")
-                            .with_timezone(&self.tz)),
-                            task.done.map(|date| date
-                                .and_time(MIDNIGHT)
-                                .and_local_timezone(task.tz)
-                                .earliest())
-                        )
-                        .red()
+                if task.status == taskparser::Status::Complete {
+                    update_date!(
+                        self,
+                        task,
+                        tc_task,
+                        ops,
+                        "Complete Date",
+                        compare_done,
+                        done
                     );
-                    tc_task.set_value(
-                        "end",
-                        task.done.map(|ed| {
-                            ed.and_time(MIDNIGHT)
-                                .and_local_timezone(self.tz)
-                                .unwrap()
-                                .to_utc()
-                                .timestamp()
-                                .to_string()
-                        }),
-                        &mut ops,
-                    )?;
                 }
-
-                if task.status == taskparser::Status::Canceled && !task.compare_canceled(&tc_task) {
-                    println!(
-                        "    {}",
-                        format!(
-                            "Canceled Date: {:?} -> {:?}",
-                            tc_task.get_value("end").map(|val| DateTime::from_timestamp(
-                                val.parse().expect("Timestamp is not valid"),
-                                0
-                            )
-                            .expect("Timestamp is not valid")
-                            .with_timezone(&self.tz)),
-                            task.canceled.map(|date| date
-                                .and_time(MIDNIGHT)
-                                .and_local_timezone(task.tz)
-                                .earliest())
-                        )
-                        .red()
+                if task.status == taskparser::Status::Canceled {
+                    update_date!(
+                        self,
+                        task,
+                        tc_task,
+                        ops,
+                        "Canceled Date",
+                        compare_canceled,
+                        canceled
                     );
-                    tc_task.set_value(
-                        "end",
-                        task.canceled.map(|ed| {
-                            ed.and_time(MIDNIGHT)
-                                .and_local_timezone(self.tz)
-                                .unwrap()
-                                .to_utc()
-                                .timestamp()
-                                .to_string()
-                        }),
-                        &mut ops,
-                    )?;
                 }
 
+                update_date!(self, task, tc_task, ops, "Until", compare_until, until);
+
                 // Update scheduled
-                if !task.compare_schedule(&tc_task) {
+                update_date!(
+                    self,
+                    task,
+                    tc_task,
+                    ops,
+                    "Scheduled",
+                    compare_schedule,
+                    scheduled
+                );
+
+                // Update priority
+                // Normal priority results in no special item in the task data
+                if !task.compare_priority(&tc_task) {
                     println!(
-                        "    {}",
+                        "      {}",
                         format!(
-                            "Start Date: {:?} -> {:?}",
-                            tc_task
-                                .get_value("scheduled")
-                                .map(|val| DateTime::from_timestamp(
-                                    val.parse().expect("Timestamp is not valid"),
-                                    0
-                                )
-                                .expect("Timestamp is not valid")
-                                .with_timezone(&self.tz)),
-                            task.start.map(|date| date
-                                .and_time(MIDNIGHT)
-                                .and_local_timezone(task.tz)
-                                .earliest())
+                            "Priority: {} -> {}",
+                            tc_task.get_value(&mapping.priority.attribute).unwrap_or(""),
+                            task.priority
                         )
                         .red()
                     );
-                    tc_task.set_value(
-                        "scheduled",
-                        task.scheduled.map(|ed| {
-                            ed.and_time(MIDNIGHT)
-                                .and_local_timezone(self.tz)
-                                .unwrap()
-                                .to_utc()
-                                .timestamp()
-                                .to_string()
-                        }),
-                        &mut ops,
-                    )?;
+                    set_priority(task, &mut tc_task, &mut ops)?;
                 }
 
-                // Update priority
-                // Normal priority results in no special item in the task data
-                if !task.compare_priority(&tc_task) {
+                // Update UDAs
+                if !task.compare_attributes(&tc_task) {
                     println!(
                         "      {}",
-                        format!("Priority: {} -> {}", tc_task.get_priority(), task.priority).red()
+                        format!("Attributes: {:?}", task.attributes).red()
                     );
-                    let pri = match task.priority {
-                        taskparser::Priority::Normal => None,
-                        taskparser::Priority::Lowest | taskparser::Priority::Low => Some("L"),
-                        taskparser::Priority::Medium => Some("M"),
-                        taskparser::Priority::High | taskparser::Priority::Highest => Some("H"),
-                    };
-                    tc_task.set_value("priority", pri.map(|x| x.to_string()), &mut ops)?;
-
-                    // If highest priority, also set the +next tag
-                    if task.priority == taskparser::Priority::Highest {
-                        tc_task.set_value("tag_next", Some(String::from("")), &mut ops)?;
-                    }
+                    set_attributes(task, &mut tc_task, &self.tz, &mut ops)?;
                 }
 
                 // Update project
@@ -336,172 +575,262 @@ impl TaskWarriorSync {
                         "    {}",
                         format!(
                             "Project: {:?} -> {:?}",
-                            tc_task.get_value("project"),
-                            task.project
+                            tc_task.get_value(&mapping.project),
+                            task.effective_project()
                         )
                         .red()
                     );
-                    tc_task.set_value("project", task.project.clone(), &mut ops)?;
+                    tc_task.set_value(
+                        &mapping.project,
+                        task.effective_project().map(String::from),
+                        &mut ops,
+                    )?;
                 }
             }
 
             if ops.is_empty() {
-                return Ok(false);
+                return Ok(new_block_id);
             }
-            return self
-                .replica
-                .commit_operations(ops)
-                .map(|_| false)
-                .context("Failed committing operations");
+            return self.commit(ops).map(|_| new_block_id);
         } else {
             // Generate UUID and create task
-            const MIDNIGHT: NaiveTime = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
-            let uuid = Uuid::new_v4();
+            let uuid = if settings.markdown.short_ids {
+                let uuid = self.new_uuid(settings.markdown.short_id_length)?;
+                task.short_id = Some(self.short_id(&uuid, settings.markdown.short_id_length)?);
+                uuid
+            } else {
+                Uuid::new_v4()
+            };
             task.uuid = Some(uuid);
+            if settings.markdown.block_ids && task.block_id.is_none() {
+                task.block_id = Some(block_id(task, &uuid));
+            }
             let mut tc_task = self.replica.create_task(uuid, &mut ops)?;
             tc_task.set_status(task.status.clone().into(), &mut ops)?;
-            tc_task.set_description(task.description.clone(), &mut ops)?;
-            tc_task.set_value(
-                "due",
-                task.due.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
-                &mut ops,
-            )?;
-            tc_task.set_value(
-                "wait",
-                task.start.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
-                &mut ops,
-            )?;
-            tc_task.set_value(
-                "scheduled",
-                task.scheduled.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
-                &mut ops,
-            )?;
-            tc_task.set_value(
-                "created",
-                task.created.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
-                &mut ops,
-            )?;
-            tc_task.set_value(
-                "end",
-                task.done.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
-                &mut ops,
-            )?;
+            tc_task.set_description(taskparser::strip_tags(&task.description), &mut ops)?;
+            for (attribute, date) in [
+                (&mapping.due, task.due),
+                (&mapping.start, task.start),
+                (&mapping.scheduled, task.scheduled),
+                (&mapping.created, task.created),
+                (&mapping.done, task.done),
+                (&mapping.canceled, task.canceled),
+                (&mapping.until, task.until),
+            ] {
+                if let Some(date) = date {
+                    tc_task.set_value(attribute, Some(date.to_timestamp(&self.tz)), &mut ops)?;
+                }
+            }
+
+            set_priority(task, &mut tc_task, &mut ops)?;
+            if task.active && mapping.tracks_active() {
+                set_active(task, &mut tc_task, &self.tz, &mut ops)?;
+            }
             tc_task.set_value(
-                "end",
-                task.canceled.map(|x| {
-                    x.and_time(MIDNIGHT)
-                        .and_local_timezone(self.tz)
-                        .unwrap()
-                        .to_utc()
-                        .timestamp()
-                        .to_string()
-                }),
+                &mapping.project,
+                task.effective_project().map(String::from),
                 &mut ops,
             )?;
 
-            let pri = match task.priority {
-                taskparser::Priority::Lowest | taskparser::Priority::Low => Some("L"),
-                taskparser::Priority::Normal => None,
-                taskparser::Priority::Medium => Some("M"),
-                taskparser::Priority::High | taskparser::Priority::Highest => Some("H"),
-            };
-            tc_task.set_value("priority", pri.map(|x| x.to_string()), &mut ops)?;
-            if task.priority == taskparser::Priority::Highest {
-                tc_task.set_value("tag_next", Some("".to_string()), &mut ops)?;
-            }
+            set_tags(task, &mut tc_task, &mut ops)?;
+            set_attributes(task, &mut tc_task, &self.tz, &mut ops)?;
 
-            tc_task.set_value("project", task.project.clone(), &mut ops)?;
+            if let Some(task_open) = link(task) {
+                let timestamp = Utc::now().timestamp();
+                let annotation = String::from(format!("annotation_{timestamp}"));
+                tc_task.set_value(annotation, Some(task_open), &mut ops)?;
+            }
+            if let Some(source) = source
+                && !mapping.source_file.is_empty()
+            {
+                tc_task.set_value(&mapping.source_file, Some(source), &mut ops)?;
+            }
 
-            for tag in &task.tags {
-                let tag_str = format!("tag_{}", tag);
-                tc_task.set_value(tag_str, Some("".to_string()), &mut ops)?;
+            self.commit(ops)?;
+            if let Some(uuids) = self.uuids.as_mut() {
+                uuids.push(uuid);
             }
 
-            if let Some(file_name) = file.as_ref().file_stem() {
-                if let Some(vault) = vault_path {
-                    if let Some(vault_name) = vault.as_ref().file_name() {
-                        let timestamp = Utc::now().timestamp();
-                        let annotation = String::from(format!("annotation_{timestamp}"));
-                        let task_open = String::from(format!(
-                            "obsidian://open?vault={}&file={}",
-                            vault_name.to_str().unwrap(),
-                            file_name.to_str().unwrap()
-                        ));
-                        tc_task.set_value(annotation, Some(task_open), &mut ops)?;
-                    }
+            return Ok(true);
+        }
+    }
+
+    // Adds up the time a task was active. Every stop in the task history holds
+    // the start time it removed. Tasks without any history of being started,
+    // like those imported from taskwarrior 2, fall back on the journal
+    // annotations taskwarrior adds with journal.time turned on.
+    pub fn tracked_time(&mut self, uuid: Uuid) -> Result<Duration> {
+        let Some(tc_task) = self.replica.get_task(uuid)? else {
+            return Ok(Duration::zero());
+        };
+        let now = Utc::now();
+        let mut tracked = Duration::zero();
+        let mut started = false;
+        for operation in self.replica.get_task_operations(uuid)? {
+            if let taskchampion::Operation::Update {
+                property,
+                old_value,
+                value,
+                timestamp,
+                ..
+            } = operation
+                && property == "start"
+            {
+                started = true;
+                if let (Some(start), None) = (old_value.as_deref().and_then(parse_timestamp), value)
+                {
+                    tracked += timestamp - start;
                 }
             }
+        }
+        if !started {
+            return Ok(journal_time(&tc_task, now));
+        }
+        if let Some(start) = tc_task.get_value("start").and_then(parse_timestamp) {
+            tracked += now - start;
+        }
+        Ok(tracked)
+    }
+
+    // Completes or deletes a task that is being unlinked from obsidian. Returns
+    // true when the task needs changing, which only happens outside a dry run.
+    pub fn unlink(&mut self, uuid: Uuid, action: &UnlinkAction, dry_run: bool) -> Result<bool> {
+        let Some(mut tc_task) = self.replica.get_task(uuid)? else {
+            return Ok(false);
+        };
+        let status = match (action, tc_task.get_status()) {
+            (_, status) if taskparser::Status::try_from(status.clone()).is_err() => {
+                return Ok(false);
+            }
+            (UnlinkAction::Complete, status) if taskparser::is_pending(&status) => {
+                taskchampion::Status::Completed
+            }
+            (UnlinkAction::Delete, status) if status != taskchampion::Status::Deleted => {
+                taskchampion::Status::Deleted
+            }
+            _ => return Ok(false),
+        };
+        if !dry_run {
+            let mut ops = taskchampion::Operations::new();
+            tc_task.set_status(status, &mut ops)?;
+            self.commit(ops)?;
+        }
+        Ok(true)
+    }
 
-            self.replica
-                .commit_operations(ops)
-                .context("Failed to commit operations")?;
+    // Fills in the uuid of a task whose markdown only holds a short id
+    pub fn resolve_short_id(&mut self, task: &mut ObsidianTask) -> Result<()> {
+        let Some(short_id) = task.short_id.as_ref().filter(|_| task.uuid.is_none()) else {
+            return Ok(());
+        };
+        let matches: Vec<Uuid> = self
+            .task_uuids()?
+            .iter()
+            .copied()
+            .filter(|uuid| uuid.simple().to_string().starts_with(short_id.as_str()))
+            .collect();
+        match matches[..] {
+            [uuid] => {
+                task.uuid = Some(uuid);
+                Ok(())
+            }
+            [] => Err(anyhow!("No task found for id {short_id}")),
+            _ => Err(anyhow!("Id {short_id} matches {} tasks", matches.len())),
+        }
+    }
 
-            return Ok(true);
+    // The shortest prefix of the uuid, at least length long, that no other
+    // task shares
+    fn short_id(&mut self, uuid: &Uuid, length: usize) -> Result<String> {
+        let simple = uuid.simple().to_string();
+        let others: Vec<String> = self
+            .task_uuids()?
+            .iter()
+            .filter(|other| *other != uuid)
+            .map(|other| other.simple().to_string())
+            .collect();
+        let length = (length.clamp(4, 32)..=32)
+            .find(|len| !others.iter().any(|other| other[..*len] == simple[..*len]))
+            .unwrap_or(32);
+        Ok(simple[..length].to_string())
+    }
+
+    // A new uuid whose short id doesn't collide with any existing task
+    fn new_uuid(&mut self, length: usize) -> Result<Uuid> {
+        let length = length.clamp(4, 32);
+        let prefixes: Vec<String> = self
+            .task_uuids()?
+            .iter()
+            .map(|uuid| uuid.simple().to_string()[..length].to_string())
+            .collect();
+        loop {
+            let uuid = Uuid::new_v4();
+            if !prefixes.contains(&uuid.simple().to_string()[..length].to_string()) {
+                return Ok(uuid);
+            }
         }
     }
 
-    pub fn tc_to_md(&mut self, task: &ObsidianTask, tz: &chrono_tz::Tz) -> Option<ObsidianTask> {
-        const MIDNIGHT: NaiveTime =
-            chrono::NaiveTime::from_hms_opt(0, 0, 0).expect("Invalid timestamp");
+    pub fn tc_to_md(&mut self, task: &ObsidianTask) -> Option<ObsidianTask> {
+        let time_tracking = task.settings.markdown.time_tracking;
+        let tracked = match task.uuid {
+            // 🛫 dates stored in start aren't time spent on the task
+            Some(uuid) if time_tracking && task.settings.mapping.tracks_active() => {
+                match self.tracked_time(uuid) {
+                    Ok(tracked) => Some(tracked).filter(|tracked| tracked.num_minutes() > 0),
+                    Err(err) => {
+                        println!("      {}", format!("{err:#}").yellow());
+                        task.tracked
+                    }
+                }
+            }
+            // Whatever is in the markdown is left alone
+            _ => task.tracked,
+        };
+        let tracked_changed =
+            tracked.map(|t| t.num_minutes()) != task.tracked.map(|t| t.num_minutes());
+        let tz = &self.tz;
+        let mapping = &task.settings.mapping;
         // Compare the task with its taskchampion version,
         // if taskchampion exists and they don't match, return
         // the new string to put in the markdown
         if let Some(uuid) = task.uuid {
             let tc_task_opt = self.replica.get_task(uuid).ok().flatten();
             if let Some(tc_task) = tc_task_opt {
-                if *task != tc_task {
+                if let Err(err) = taskparser::Status::try_from(tc_task.get_status()) {
+                    println!("      {}", format!("{err:#}, skipping").yellow());
+                    return None;
+                }
+                if *task != tc_task || tracked_changed {
+                    if tracked_changed {
+                        println!(
+                            "{}",
+                            format!("      Tracked: {:?} -> {:?}", task.tracked, tracked).yellow()
+                        );
+                    }
                     if !task.compare_due(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, due, "due");
+                        print_date_diff!(tz, task, tc_task, due, &mapping.due);
                     }
                     if !task.compare_schedule(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, scheduled, "scheduled");
+                        print_date_diff!(tz, task, tc_task, scheduled, &mapping.scheduled);
                     }
                     if !task.compare_start(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, start, "wait");
+                        print_date_diff!(tz, task, tc_task, start, &mapping.start);
                     }
                     if !task.compare_created(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, created, "created");
+                        print_date_diff!(tz, task, tc_task, created, &mapping.created);
                     }
                     if !task.compare_done(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, done, "end");
+                        print_date_diff!(tz, task, tc_task, done, &mapping.done);
                     }
                     if !task.compare_canceled(&tc_task) {
-                        print_date_diff!(tz, task, tc_task, canceled, "end");
+                        print_date_diff!(tz, task, tc_task, canceled, &mapping.canceled);
+                    }
+                    if !task.compare_until(&tc_task) {
+                        print_date_diff!(tz, task, tc_task, until, &mapping.until);
+                    }
+                    if !task.compare_expired(&tc_task) {
+                        println!("{}", "      Expired".yellow());
                     }
                     if !task.compare_status(&tc_task) {
                         println!(
@@ -514,7 +843,7 @@ impl TaskWarriorSync {
                             "{}",
                             format!(
                                 "      {} -> {}",
-                                task.description,
+                                taskparser::strip_tags(&task.description),
                                 tc_task.get_description()
                             )
                             .yellow()
@@ -523,8 +852,12 @@ impl TaskWarriorSync {
                     if !task.compare_priority(&tc_task) {
                         println!(
                             "{}",
-                            format!("      {} -> {}", task.priority, tc_task.get_priority())
-                                .yellow()
+                            format!(
+                                "      {} -> {}",
+                                task.priority,
+                                tc_task.get_value(&mapping.priority.attribute).unwrap_or("")
+                            )
+                            .yellow()
                         );
                     }
                     if !task.compare_project(&tc_task) {
@@ -532,14 +865,26 @@ impl TaskWarriorSync {
                             "{}",
                             format!(
                                 "      {:?} -> {:?}",
-                                task.project,
-                                tc_task.get_value("project")
+                                task.effective_project(),
+                                tc_task.get_value(&mapping.project)
                             )
                             .yellow()
                         );
                     }
-                    let obsidian_task = ObsidianTask::from(tc_task);
-                    return Some(obsidian_task.with_tz(&self.tz));
+                    // If the description is unchanged, keep the tags where they were
+                    // written in the markdown
+                    let description_changed = !task.compare_description(&tc_task);
+                    let mut obsidian_task =
+                        ObsidianTask::from_tc(tc_task, &self.tz, &task.settings);
+                    obsidian_task.inherit_defaults(task);
+                    obsidian_task.marker = task.marker.clone();
+                    obsidian_task.short_id = task.short_id.clone();
+                    obsidian_task.block_id = task.block_id.clone();
+                    obsidian_task.tracked = tracked;
+                    if !description_changed {
+                        obsidian_task.description = task.retag_description(&obsidian_task.tags);
+                    }
+                    return Some(obsidian_task);
                 }
             }
         }
@@ -554,6 +899,26 @@ pub struct UpdateContext {
 }
 
 pub fn update_obsidian_tasks<T: AsRef<Path>>(path: T, updates: &[UpdateContext]) -> Result<()> {
+    let file_string = fs::read_to_string(&path)?;
+    let file_lines: Vec<&str> = file_string.lines().collect();
+
+    let mut lines = Vec::with_capacity(updates.len());
+    for update in updates {
+        // Keep the indentation and any blockquote or callout prefix
+        let trimmed =
+            file_lines[update.line].trim_start_matches(|c: char| c.is_whitespace() || c == '>');
+        let prefix_len = file_lines[update.line].len() - trimmed.len();
+        let prefix = &file_lines[update.line][0..prefix_len];
+        lines.push((
+            update.line,
+            format!("{}{}", prefix, update.task.to_string()),
+        ));
+    }
+    update_lines(path, &lines)
+}
+
+// Replaces whole lines of a file, given as line numbers and their new text
+pub fn update_lines<T: AsRef<Path>>(path: T, updates: &[(usize, String)]) -> Result<()> {
     // If temp file already exists, delete it
     let temp_path = path.as_ref().with_extension(".temp");
     if temp_path.exists() {
@@ -565,15 +930,8 @@ pub fn update_obsidian_tasks<T: AsRef<Path>>(path: T, updates: &[UpdateContext])
     let mut file_lines: Vec<&str> = file_string.lines().collect();
 
     // Iterate through updates and replace those lines
-    let mut tasks = Vec::with_capacity(updates.len());
-    for update in updates {
-        let trimmed = file_lines[update.line].trim_start();
-        let whitespace_len = file_lines[update.line].len() - trimmed.len();
-        let whitespace = &file_lines[update.line][0..whitespace_len];
-        tasks.push(format!("{}{}", whitespace, update.task.to_string()));
-    }
-    for (index, update) in updates.iter().enumerate() {
-        file_lines[update.line] = &tasks[index];
+    for (line, text) in updates {
+        file_lines[*line] = text;
     }
 
     // Write to temp file
@@ -592,29 +950,27 @@ pub fn update_obsidian_tasks<T: AsRef<Path>>(path: T, updates: &[UpdateContext])
 
 #[cfg(test)]
 mod tests {
-    
-    
 
+    use crate::config::TagPlacement;
     use crate::taskparser::ObsidianTaskBuilder;
     use crate::taskparser::Priority;
     use crate::testutil::{TaskBuilder, TestContext, create_mem_replica};
     use chrono_tz::UTC;
-    
-    
-    
 
     use super::*;
 
-    use pretty_assertions::assert_eq;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     #[test]
     fn test_file_update() {
-        std::fs::remove_file("test.md");
+        // Left over from an earlier run that failed
+        let _ = std::fs::remove_file("test.md");
         let mut test_file = std::fs::File::create_new("test.md").unwrap();
-        writeln!(test_file, "This is a normal line");
-        writeln!(test_file, "- [ ] This is a test");
-        writeln!(test_file, "Another normal line");
-        writeln!(test_file, "    - [ ] This is a second test");
+        writeln!(test_file, "This is a normal line").unwrap();
+        writeln!(test_file, "- [ ] This is a test").unwrap();
+        writeln!(test_file, "Another normal line").unwrap();
+        writeln!(test_file, "    - [ ] This is a second test").unwrap();
+        writeln!(test_file, "> - [ ] This is a quoted test").unwrap();
 
         let obsidian_task = ObsidianTaskBuilder::new()
             .description("This is a passed test")
@@ -630,6 +986,10 @@ mod tests {
                 line: 3,
                 task: obsidian_task.clone(),
             },
+            UpdateContext {
+                line: 4,
+                task: obsidian_task.clone(),
+            },
         ];
 
         assert!(update_obsidian_tasks("test.md", &context).is_ok());
@@ -637,9 +997,9 @@ mod tests {
         let updated_content = std::fs::read_to_string("test.md").unwrap();
         assert_eq!(
             updated_content,
-            "This is a normal line\n- [x] This is a passed test\nAnother normal line\n    - [x] This is a passed test\n"
+            "This is a normal line\n- [x] This is a passed test\nAnother normal line\n    - [x] This is a passed test\n> - [x] This is a passed test\n"
         );
-        std::fs::remove_file("test.md");
+        std::fs::remove_file("test.md").unwrap();
     }
 
     #[test]
@@ -778,6 +1138,1007 @@ mod tests {
         assert_eq!("obsidian://open?vault=test2&file=test1", annotation);
     }
 
+    #[test]
+    fn test_due_time_round_trip() {
+        let mut o_task = ObsidianTaskBuilder::new()
+            .tz(chrono_tz::America::Chicago)
+            .description("Test")
+            .due_str("2025-06-13 14:00")
+            .build();
+
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &chrono_tz::America::Chicago);
+        assert!(ts.md_to_tc(&mut o_task, "", None).unwrap());
+
+        let uuid = ts.replica.all_task_uuids().unwrap()[0];
+        let task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(
+            task.get_due().unwrap().timestamp(),
+            chrono::DateTime::parse_from_rfc3339("2025-06-13T19:00:00Z")
+                .unwrap()
+                .timestamp()
+        );
+        assert_eq!(o_task, task);
+        assert!(ts.tc_to_md(&o_task).is_none());
+
+        // A time set in taskwarrior is carried back into the markdown
+        let mut ops = taskchampion::Operations::new();
+        let mut task = ts.replica.get_task(uuid).unwrap().unwrap();
+        task.set_due(
+            Some(
+                "2025-06-14 09:30"
+                    .parse::<TaskDate>()
+                    .unwrap()
+                    .to_utc(&chrono_tz::America::Chicago),
+            ),
+            &mut ops,
+        )
+        .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+
+        let updated = ts.tc_to_md(&o_task).unwrap();
+        assert_eq!(updated.due, Some("2025-06-14 09:30".parse().unwrap()));
+        assert_eq!(
+            updated.to_string(),
+            format!("- [ ] Test 📅 2025-06-14 09:30 [[uuid: {uuid}|⚔️]]")
+        );
+    }
+
+    #[test]
+    fn test_priority_round_trip() {
+        let mut custom = crate::config::Settings::default();
+        custom.mapping.priority.lowest = String::from("VL");
+        custom.mapping.priority.highest_tag = String::new();
+        custom.mapping.priority.highest = String::from("VH");
+        custom.mapping.priority.exact_attribute = String::new();
+
+        let priorities = [
+            Priority::Lowest,
+            Priority::Low,
+            Priority::Normal,
+            Priority::Medium,
+            Priority::High,
+            Priority::Highest,
+        ];
+        for settings in [crate::config::Settings::default(), custom] {
+            let settings = std::rc::Rc::new(settings);
+            let replica = create_mem_replica();
+            let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+            for priority in &priorities {
+                let mut task = ObsidianTaskBuilder::new()
+                    .settings(settings.clone())
+                    .description("Test")
+                    .priority(priority.clone())
+                    .build();
+                assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+                // Repeated syncs in both directions, through the written
+                // line, must not change the priority
+                for _ in 0..3 {
+                    task = taskparser::parse(task.to_string(), &UTC, &settings).unwrap();
+                    assert_eq!(&task.priority, priority);
+                    let uuid = task.uuid.unwrap();
+                    let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+                    assert!(ts.tc_to_md(&task).is_none());
+                    let from_tc = ObsidianTask::from_tc(tc_task, &UTC, &settings);
+                    assert_eq!(&from_tc.priority, priority);
+                    assert!(!ts.md_to_tc(&mut task, "", None).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_priority_changed_in_tc() {
+        let mut replica = create_mem_replica();
+        let mut context = TestContext::new(&mut replica);
+        let tc_task = TaskBuilder::new(&mut context)
+            .desc("Test task")
+            .status(taskchampion::Status::Pending)
+            .build();
+
+        let mut task = ObsidianTaskBuilder::new()
+            .uuid(tc_task.get_uuid())
+            .description("Test task")
+            .priority(Priority::Lowest)
+            .build();
+
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let mut tc_task = ts.replica.get_task(task.uuid.unwrap()).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("obsidian_priority"), Some("lowest"));
+
+        // Raising the priority in taskwarrior makes the stale exact value irrelevant
+        let mut ops = taskchampion::Operations::new();
+        tc_task.set_priority(String::from("M"), &mut ops).unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        assert_eq!(ts.tc_to_md(&task).unwrap().priority, Priority::Medium);
+    }
+
+    #[test]
+    fn test_nested_tags() {
+        for nested in [NestedTags::Separator, NestedTags::Attribute] {
+            let mut settings = crate::config::Settings::default();
+            settings.mapping.tags.nested = nested.clone();
+            let settings = std::rc::Rc::new(settings);
+
+            let mut task = taskparser::parse(
+                String::from("- [ ] Call the client #area/work/client #home"),
+                &UTC,
+                &settings,
+            )
+            .unwrap();
+
+            let replica = create_mem_replica();
+            let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+            assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+            let uuid = task.uuid.unwrap();
+            let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            let mut tc_tags: Vec<String> = tc_task
+                .get_tags()
+                .filter(|tag| tag.is_user())
+                .map(|tag| tag.to_string())
+                .collect();
+            tc_tags.sort();
+            match nested {
+                NestedTags::Attribute => {
+                    assert_eq!(tc_tags, ["area", "client", "home", "work"]);
+                    assert_eq!(
+                        tc_task.get_value("obsidian_tags"),
+                        Some("area/work/client home")
+                    );
+                }
+                _ => assert_eq!(tc_tags, ["area.work.client", "home"]),
+            }
+            assert_eq!(task, tc_task);
+            assert!(ts.tc_to_md(&task).is_none());
+
+            let mut from_tc = ObsidianTask::from_tc(tc_task, &UTC, &settings);
+            from_tc.tags.sort();
+            assert_eq!(from_tc.tags, ["area/work/client", "home"]);
+        }
+    }
+
+    #[test]
+    fn test_inline_tags() {
+        for placement in [TagPlacement::End, TagPlacement::Parentheses] {
+            let mut settings = crate::config::Settings::default();
+            settings.markdown.tag_placement = placement.clone();
+            let settings = std::rc::Rc::new(settings);
+
+            let mut task = taskparser::parse(
+                String::from("- [ ] Call #client about the #area/work report"),
+                &UTC,
+                &settings,
+            )
+            .unwrap();
+
+            let replica = create_mem_replica();
+            let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+            assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+            // The tags are kept out of the taskchampion description
+            let uuid = task.uuid.unwrap();
+            let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            assert_eq!(tc_task.get_description(), "Call about the report");
+            assert!(ts.tc_to_md(&task).is_none());
+            assert!(!ts.md_to_tc(&mut task, "", None).unwrap());
+
+            // Tags added in taskwarrior are placed according to the settings while
+            // removed tags are taken out of the line
+            let mut ops = taskchampion::Operations::new();
+            tc_task
+                .set_value("tag_urgent", Some(String::new()), &mut ops)
+                .unwrap();
+            tc_task.set_value("tag_client", None, &mut ops).unwrap();
+            ts.replica.commit_operations(ops).unwrap();
+
+            let updated = ts.tc_to_md(&task).unwrap();
+            match placement {
+                TagPlacement::End => assert_eq!(
+                    updated.description,
+                    "Call about the #area/work report #urgent"
+                ),
+                TagPlacement::Parentheses => assert_eq!(
+                    updated.description,
+                    "Call about the #area/work report (#urgent)"
+                ),
+            }
+
+            // Syncing the updated line back doesn't grow the description
+            let mut updated = taskparser::parse(updated.to_string(), &UTC, &settings).unwrap();
+            assert!(ts.tc_to_md(&updated).is_none());
+            let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            assert_eq!(tc_task.get_description(), "Call about the report");
+        }
+    }
+
+    #[test]
+    fn test_inline_tags_upgrade() {
+        // Older versions stored the inline tags in the taskchampion description
+        let mut replica = create_mem_replica();
+        let mut context = TestContext::new(&mut replica);
+        let tc_task = TaskBuilder::new(&mut context)
+            .desc("Call #client about")
+            .status(taskchampion::Status::Pending)
+            .tags(&["client"])
+            .build();
+        let uuid = tc_task.get_uuid();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+
+        let task = ObsidianTask::from_tc(tc_task, &UTC, &std::rc::Rc::default());
+        assert_eq!(task.description, "Call #client about");
+
+        let line = format!("- [ ] Call #client about [[uuid: {uuid}|⚔️]]");
+        let mut task = taskparser::parse(line, &UTC, &std::rc::Rc::default()).unwrap();
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Syncing the line takes the tags out of the stored description
+        assert!(!ts.md_to_tc(&mut task, "", None).unwrap());
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_description(), "Call about");
+        assert!(ts.tc_to_md(&task).is_none());
+    }
+
+    #[test]
+    fn test_custom_mapping() {
+        let mut settings = crate::config::Settings::default();
+        settings.mapping.start = String::from("start");
+        settings.mapping.priority.attribute = String::from("importance");
+        settings.mapping.priority.highest = String::from("critical");
+        settings.mapping.priority.highest_tag = String::new();
+        let settings = std::rc::Rc::new(settings);
+
+        let mut task = ObsidianTaskBuilder::new()
+            .settings(settings.clone())
+            .description("Test")
+            .start_str("2025-06-08")
+            .priority(Priority::Highest)
+            .build();
+
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("start"), Some("1749340800"));
+        assert_eq!(tc_task.get_value("wait"), None);
+        assert_eq!(tc_task.get_value("importance"), Some("critical"));
+        assert_eq!(tc_task.get_value("priority"), None);
+        assert_eq!(tc_task.get_tags().filter(|tag| tag.is_user()).count(), 0);
+        assert_eq!(task, tc_task);
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Changes to the mapped attributes come back into the markdown
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("importance", Some(String::from("M")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert_eq!(updated.priority, Priority::Medium);
+        assert_eq!(updated.start, task.start);
+    }
+
+    #[test]
+    fn test_inferred_project() {
+        let mut task = ObsidianTaskBuilder::new()
+            .description("Test")
+            .inferred_project(Some("Work.ClientA"))
+            .build();
+
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("project"), Some("Work.ClientA"));
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // The inferred project isn't written into the markdown
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("description", Some(String::from("Renamed")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert!(!updated.to_string().contains("🔨"));
+
+        // An explicit project wins and is kept in the markdown
+        let mut task = ObsidianTaskBuilder::new()
+            .uuid(uuid)
+            .description("Renamed")
+            .project(Some("Personal"))
+            .inferred_project(Some("Work.ClientA"))
+            .build();
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("project"), Some("Personal"));
+
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("description", Some(String::from("Again")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert!(updated.to_string().contains("🔨 Personal"));
+    }
+
+    #[test]
+    fn test_note_defaults() {
+        let defaults = std::rc::Rc::new(crate::note::NoteDefaults {
+            tags: vec![String::from("client")],
+            priority: Some(Priority::High),
+            ..crate::note::NoteDefaults::default()
+        });
+        let mut task = ObsidianTaskBuilder::new()
+            .description("Test #own")
+            .tags(&["own"])
+            .defaults(defaults.clone())
+            .build();
+
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("priority"), Some("H"));
+        assert!(tc_task.get_value("tag_client").is_some());
+        assert!(tc_task.get_value("tag_own").is_some());
+        assert_eq!(task, tc_task);
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // The defaults aren't written back into the markdown
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("description", Some(String::from("Renamed")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert_eq!(
+            updated.to_string(),
+            format!("- [ ] Renamed #own [[uuid: {uuid}|⚔️]]")
+        );
+        assert_eq!(updated.tags, ["own"]);
+        assert_eq!(updated, ts.replica.get_task(uuid).unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_short_ids() {
+        let mut settings = crate::config::Settings::default();
+        settings.markdown.short_ids = true;
+        let settings = std::rc::Rc::new(settings);
+
+        let mut task = ObsidianTaskBuilder::new()
+            .settings(settings.clone())
+            .description("Test")
+            .build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+
+        let uuid = task.uuid.unwrap();
+        let short_id = task.short_id.clone().unwrap();
+        assert_eq!(short_id, uuid.simple().to_string()[..8]);
+        assert_eq!(
+            task.to_string(),
+            format!("- [ ] Test [[uuid: {short_id}|⚔️]]")
+        );
+
+        // The short id is resolved back to the full uuid
+        let mut parsed = taskparser::parse(task.to_string(), &UTC, &settings).unwrap();
+        assert_eq!(parsed.uuid, None);
+        ts.resolve_short_id(&mut parsed).unwrap();
+        assert_eq!(parsed.uuid, Some(uuid));
+        assert!(ts.tc_to_md(&parsed).is_none());
+
+        // Changes from taskwarrior keep the short id
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("description", Some(String::from("Renamed")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let updated = ts.tc_to_md(&parsed).unwrap();
+        assert_eq!(
+            updated.to_string(),
+            format!("- [ ] Renamed [[uuid: {short_id}|⚔️]]")
+        );
+
+        // A task sharing the prefix makes the short id longer
+        let mut colliding = uuid.simple().to_string();
+        colliding.replace_range(8..9, if &colliding[8..9] == "0" { "1" } else { "0" });
+        let colliding = Uuid::parse_str(&colliding).unwrap();
+        let mut ops = taskchampion::Operations::new();
+        ts.replica.create_task(colliding, &mut ops).unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        // The uuids are read once per run, so the next run sees it
+        let mut ts = TaskWarriorSync::from_replica(ts.replica, &UTC);
+        assert_eq!(
+            ts.short_id(&uuid, 8).unwrap(),
+            uuid.simple().to_string()[..9]
+        );
+
+        let mut ambiguous = parsed.clone();
+        ambiguous.uuid = None;
+        assert!(ts.resolve_short_id(&mut ambiguous).is_err());
+
+        let mut unknown = parsed.clone();
+        unknown.uuid = None;
+        unknown.short_id = Some(String::from("ffffffff"));
+        assert!(ts.resolve_short_id(&mut unknown).is_err());
+
+        // New uuids never share the prefix of an existing task
+        let new_uuid = ts.new_uuid(8).unwrap();
+        assert_ne!(new_uuid.simple().to_string()[..8], short_id);
+    }
+
+    #[test]
+    fn test_active() {
+        let mut task = ObsidianTaskBuilder::new().description("Test").build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+        let uuid = task.uuid.unwrap();
+
+        // Starting the task in taskwarrior shows it as active
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task.start(&mut ops).unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let started = ts.tc_to_md(&task).unwrap();
+        assert_eq!(
+            started.to_string(),
+            format!("- [/] Test [[uuid: {uuid}|⚔️]]")
+        );
+        assert!(ts.tc_to_md(&started).is_none());
+
+        // Unchecking it in markdown stops it
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert!(!tc_task.is_active());
+
+        // The start time in the marker is kept in taskwarrior
+        let mut settings = crate::config::Settings::default();
+        settings.markdown.active_format = crate::config::ActiveFormat::Marker;
+        let settings = std::rc::Rc::new(settings);
+        let line = format!("- [ ] Test ▶️ 2025-06-08 14:30 [[uuid: {uuid}|⚔️]]");
+        let mut task = taskparser::parse(line.clone(), &UTC, &settings).unwrap();
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert!(tc_task.is_active());
+        assert_eq!(tc_task.get_value("start"), Some("1749393000"));
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Completing the task stops it
+        let mut done = task.clone();
+        done.status = taskparser::Status::Complete;
+        done.active = false;
+        ts.md_to_tc(&mut done, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert!(!tc_task.is_active());
+
+        // 🛫 stored in start turns the active state off
+        let mut settings = crate::config::Settings::default();
+        settings.mapping.start = String::from("start");
+        let settings = std::rc::Rc::new(settings);
+        let line = format!("- [ ] Test 🛫 2025-06-08 [[uuid: {uuid}|⚔️]]");
+        let mut task = taskparser::parse(line.clone(), &UTC, &settings).unwrap();
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert!(tc_task.is_active());
+        assert!(ts.tc_to_md(&task).is_none());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut settings = crate::config::Settings::default();
+        settings.uda.insert(
+            String::from("estimate"),
+            crate::config::Uda {
+                kind: crate::config::UdaType::Duration,
+                emoji: Some(String::from("⏲️")),
+            },
+        );
+        settings.uda.insert(
+            String::from("review"),
+            crate::config::Uda {
+                kind: crate::config::UdaType::Date,
+                emoji: None,
+            },
+        );
+        let settings = std::rc::Rc::new(settings);
+        let mut task = taskparser::parse(
+            String::from("- [ ] Test ⏲️ 3h [review:: 2025-06-10]"),
+            &UTC,
+            &settings,
+        )
+        .unwrap();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_description(), "Test");
+        assert_eq!(tc_task.get_value("estimate"), Some("PT3H"));
+        assert_eq!(tc_task.get_value("review"), Some("1749513600"));
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Values written differently in taskwarrior are left alone
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("estimate", Some(String::from("PT180M")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        assert!(ts.tc_to_md(&task).is_none());
+        assert!(!ts.md_to_tc(&mut task, "", None).unwrap());
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("estimate"), Some("PT180M"));
+
+        // Changes in taskwarrior show up in the markdown
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("estimate", Some(String::from("PT1H30M")), &mut ops)
+            .unwrap();
+        tc_task.set_value("review", None, &mut ops).unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert_eq!(
+            updated.to_string(),
+            format!("- [ ] Test ⏲️ 1h30m [[uuid: {uuid}|⚔️]]")
+        );
+
+        // And removing them in markdown removes them in taskwarrior
+        let mut removed = updated.clone();
+        removed.attributes.clear();
+        ts.md_to_tc(&mut removed, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("estimate"), None);
+
+        // Seconds survive the trip through the markdown
+        for (stored, written) in [("PT1M30S", "1m30s"), ("PT30S", "30s")] {
+            let mut ops = taskchampion::Operations::new();
+            let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            tc_task
+                .set_value("estimate", Some(String::from(stored)), &mut ops)
+                .unwrap();
+            ts.replica.commit_operations(ops).unwrap();
+            let updated = ts.tc_to_md(&removed).unwrap();
+            assert_eq!(
+                updated.to_string(),
+                format!("- [ ] Test ⏲️ {written} [[uuid: {uuid}|⚔️]]")
+            );
+            let mut reparsed = taskparser::parse(updated.to_string(), &UTC, &settings).unwrap();
+            assert!(!ts.md_to_tc(&mut reparsed, "", None).unwrap());
+            let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            assert_eq!(tc_task.get_value("estimate"), Some(stored));
+        }
+    }
+
+    #[test]
+    fn test_until() {
+        let mut task = ObsidianTaskBuilder::new()
+            .description("Test")
+            .until_str("2099-06-10")
+            .build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("until"), Some("4084732800"));
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Expired tasks are canceled by default
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        tc_task
+            .set_value("until", Some(String::from("1749513600")), &mut ops)
+            .unwrap();
+        ts.replica.commit_operations(ops).unwrap();
+        let expired = ts.tc_to_md(&task).unwrap();
+        assert_eq!(
+            expired.to_string(),
+            format!("- [-] Test ❌ 2025-06-10 ⌛ 2025-06-10 [[uuid: {uuid}|⚔️]]")
+        );
+        // The line stays the same until the cancel is synced back
+        assert!(ts.tc_to_md(&expired).is_some_and(|task| task == expired));
+
+        for (expired, line) in [
+            (
+                crate::config::Expired::Tag,
+                format!("- [ ] Test #expired ⌛ 2025-06-10 [[uuid: {uuid}|⚔️]]"),
+            ),
+            (
+                crate::config::Expired::Keep,
+                format!("- [ ] Test ⌛ 2025-06-10 [[uuid: {uuid}|⚔️]]"),
+            ),
+        ] {
+            let mut settings = crate::config::Settings::default();
+            settings.markdown.expired = expired;
+            let settings = std::rc::Rc::new(settings);
+            let mut task = task.clone();
+            task.until = Some("2025-06-10".parse().unwrap());
+            task.settings = settings.clone();
+            match ts.tc_to_md(&task) {
+                Some(updated) => assert_eq!(updated.to_string(), line),
+                None => assert_eq!(task.to_string(), line),
+            }
+            let parsed = taskparser::parse(line, &UTC, &settings).unwrap();
+            assert!(parsed.compare_expired(&ts.replica.get_task(uuid).unwrap().unwrap()));
+        }
+
+        // Canceling it in markdown deletes it in taskwarrior
+        let mut expired = expired.clone();
+        ts.md_to_tc(&mut expired, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_status(), taskchampion::Status::Deleted);
+    }
+
+    #[test]
+    fn test_sync_server() {
+        let server_dir = testdir::testdir!();
+        let server = || {
+            taskchampion::ServerConfig::Local {
+                server_dir: server_dir.clone(),
+            }
+            .into_server()
+            .unwrap()
+        };
+        let mut task = ObsidianTaskBuilder::new().description("Test").build();
+        let mut ts = TaskWarriorSync::from_replica(create_mem_replica(), &UTC);
+        let mut other = TaskWarriorSync::from_replica(create_mem_replica(), &UTC);
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        ts.sync_server(&mut server()).unwrap();
+
+        other.sync_server(&mut server()).unwrap();
+        let uuid = task.uuid.unwrap();
+        let mut tc_task = other.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_description(), "Test");
+
+        // Changes made elsewhere come back the same way
+        let mut ops = taskchampion::Operations::new();
+        tc_task
+            .set_status(taskchampion::Status::Completed, &mut ops)
+            .unwrap();
+        other.replica.commit_operations(ops).unwrap();
+        other.sync_server(&mut server()).unwrap();
+        ts.sync_server(&mut server()).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_status(), taskchampion::Status::Completed);
+    }
+
+    #[test]
+    fn test_undo_and_working_set() {
+        let mut first = ObsidianTaskBuilder::new().description("First").build();
+        let mut second = ObsidianTaskBuilder::new().description("Second").build();
+        let mut ts = TaskWarriorSync::from_replica(create_mem_replica(), &UTC);
+        assert!(!ts.has_changes());
+        ts.md_to_tc(&mut first, "", None).unwrap();
+        assert!(ts.has_changes());
+        ts.rebuild_working_set().unwrap();
+        let first_uuid = first.uuid.unwrap();
+        assert!(
+            ts.replica
+                .working_set()
+                .unwrap()
+                .by_uuid(first_uuid)
+                .is_some()
+        );
+
+        // A later run gets an undo point of its own
+        let mut ts = TaskWarriorSync::from_replica(ts.replica, &UTC);
+        first.status = taskparser::Status::Complete;
+        ts.md_to_tc(&mut first, "", None).unwrap();
+        ts.md_to_tc(&mut second, "", None).unwrap();
+        ts.rebuild_working_set().unwrap();
+        let second_uuid = second.uuid.unwrap();
+        let working_set = ts.replica.working_set().unwrap();
+        assert!(working_set.by_uuid(first_uuid).is_none());
+        assert!(working_set.by_uuid(second_uuid).is_some());
+
+        // Undoing it reverts every change of the run, and only those
+        let undo = ts.replica.get_undo_operations().unwrap();
+        assert_eq!(undo[0], taskchampion::Operation::UndoPoint);
+        assert_eq!(undo.iter().filter(|op| op.is_undo_point()).count(), 1);
+        assert!(ts.replica.commit_reversed_operations(undo).unwrap());
+        assert!(ts.replica.get_task(second_uuid).unwrap().is_none());
+        let tc_task = ts.replica.get_task(first_uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_status(), taskchampion::Status::Pending);
+    }
+
+    #[test]
+    fn test_statuses() {
+        let mut task = ObsidianTaskBuilder::new().description("Test").build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+        let uuid = task.uuid.unwrap();
+        let set_status = |ts: &mut TaskWarriorSync, status: &str| {
+            let mut ops = taskchampion::Operations::new();
+            let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            tc_task
+                .set_value("status", Some(String::from(status)), &mut ops)
+                .unwrap();
+            ts.replica.commit_operations(ops).unwrap();
+        };
+
+        // Taskwarrior 2 waiting tasks are pending, even once the wait is over
+        set_status(&mut ts, "waiting");
+        assert!(ts.tc_to_md(&task).is_none());
+        let mut complete = task.clone();
+        complete.status = taskparser::Status::Complete;
+        ts.md_to_tc(&mut complete, "", None).unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_status(), taskchampion::Status::Completed);
+
+        // Recurring templates and unknown statuses are left alone either way
+        for status in ["recurring", "someday"] {
+            set_status(&mut ts, status);
+            assert!(ts.tc_to_md(&task).is_none());
+            ts.md_to_tc(&mut complete, "", None).unwrap();
+            assert!(!ts.unlink(uuid, &UnlinkAction::Delete, false).unwrap());
+            let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+            assert_eq!(tc_task.get_value("status"), Some(status));
+        }
+    }
+
+    #[test]
+    fn test_tracked_time() {
+        let mut settings = crate::config::Settings::default();
+        settings.markdown.time_tracking = true;
+        let settings = std::rc::Rc::new(settings);
+        let mut task = ObsidianTaskBuilder::new()
+            .settings(settings.clone())
+            .description("Test")
+            .build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(ts.md_to_tc(&mut task, "", None).unwrap());
+        let uuid = task.uuid.unwrap();
+        assert!(ts.tc_to_md(&task).is_none());
+
+        // Two sessions of an hour and a quarter hour
+        let start = DateTime::from_timestamp(1749393000, 0).unwrap();
+        let mut ops = taskchampion::Operations::new();
+        for (from, to) in [(0, 60), (120, 135)] {
+            let begin = start + Duration::minutes(from);
+            ops.push(taskchampion::Operation::Update {
+                uuid,
+                property: String::from("start"),
+                old_value: None,
+                value: Some(begin.timestamp().to_string()),
+                timestamp: begin,
+            });
+            ops.push(taskchampion::Operation::Update {
+                uuid,
+                property: String::from("start"),
+                old_value: Some(begin.timestamp().to_string()),
+                value: None,
+                timestamp: start + Duration::minutes(to),
+            });
+        }
+        ts.replica.commit_operations(ops).unwrap();
+        assert_eq!(ts.tracked_time(uuid).unwrap(), Duration::minutes(75));
+
+        let updated = ts.tc_to_md(&task).unwrap();
+        assert_eq!(
+            updated.to_string(),
+            format!("- [ ] Test ⏱ 1h15m [[uuid: {uuid}|⚔️]]")
+        );
+        let parsed = taskparser::parse(updated.to_string(), &UTC, &settings).unwrap();
+        assert_eq!(parsed.tracked, Some(Duration::minutes(75)));
+        assert!(ts.tc_to_md(&parsed).is_none());
+
+        // The tracked time is never synced back
+        let mut parsed = parsed.clone();
+        assert!(!ts.md_to_tc(&mut parsed, "", None).unwrap());
+        assert_eq!(ts.tracked_time(uuid).unwrap(), Duration::minutes(75));
+    }
+
+    #[test]
+    fn test_journal_time() {
+        let mut task = ObsidianTaskBuilder::new().description("Test").build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        ts.md_to_tc(&mut task, "", None).unwrap();
+        let uuid = task.uuid.unwrap();
+
+        let mut ops = taskchampion::Operations::new();
+        let mut tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        for (minutes, description) in [
+            (0, "Started task"),
+            (30, "Stopped task"),
+            (40, "Some note"),
+            (60, "Started task"),
+            (100, "Stopped task"),
+        ] {
+            tc_task
+                .add_annotation(
+                    taskchampion::Annotation {
+                        entry: DateTime::from_timestamp(1749393000 + minutes * 60, 0).unwrap(),
+                        description: String::from(description),
+                    },
+                    &mut ops,
+                )
+                .unwrap();
+        }
+        ts.replica.commit_operations(ops).unwrap();
+        assert_eq!(ts.tracked_time(uuid).unwrap(), Duration::minutes(70));
+    }
+
+    #[test]
+    fn test_obsidian_link() {
+        let vault = Path::new("/notes/My Vault");
+        let file = vault.join("Work/Acme.md");
+        assert_eq!(
+            obsidian_link(&file, Some(vault), None, LinkFormat::Open).unwrap(),
+            "obsidian://open?vault=My%20Vault&file=Work%2FAcme"
+        );
+        assert_eq!(
+            obsidian_link(&file, Some(vault), Some("tw-a80c42ce"), LinkFormat::Open).unwrap(),
+            "obsidian://open?vault=My%20Vault&file=Work%2FAcme%23%5Etw-a80c42ce"
+        );
+        assert_eq!(
+            obsidian_link(
+                &file,
+                Some(vault),
+                Some("tw-a80c42ce"),
+                LinkFormat::AdvancedUri
+            )
+            .unwrap(),
+            "obsidian://advanced-uri?vault=My%20Vault&filepath=Work%2FAcme.md&block=tw-a80c42ce"
+        );
+        assert_eq!(obsidian_link(&file, None, None, LinkFormat::Open), None);
+    }
+
+    #[test]
+    fn test_block_ids() {
+        let mut settings = crate::config::Settings::default();
+        settings.markdown.block_ids = true;
+        let settings = std::rc::Rc::new(settings);
+
+        let mut task = ObsidianTaskBuilder::new()
+            .settings(settings.clone())
+            .description("Test")
+            .build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(
+            ts.md_to_tc(&mut task, "/vault/Inbox.md", Some("/vault"))
+                .unwrap()
+        );
+
+        let uuid = task.uuid.unwrap();
+        let block_id = format!("tw-{}", &uuid.simple().to_string()[..8]);
+        assert_eq!(task.block_id.as_deref(), Some(block_id.as_str()));
+        assert_eq!(
+            task.to_string(),
+            format!("- [ ] Test [[uuid: {uuid}|⚔️]] ^{block_id}")
+        );
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        let annotations: Vec<String> = tc_task
+            .get_annotations()
+            .map(|annotation| annotation.description)
+            .collect();
+        assert_eq!(
+            annotations,
+            [format!(
+                "obsidian://open?vault=vault&file=Inbox%23%5E{block_id}"
+            )]
+        );
+
+        // The block id survives a round trip and nothing changes
+        let mut parsed = taskparser::parse(task.to_string(), &UTC, &settings).unwrap();
+        assert!(
+            !ts.md_to_tc(&mut parsed, "/vault/Inbox.md", Some("/vault"))
+                .unwrap()
+        );
+        assert!(ts.tc_to_md(&parsed).is_none());
+
+        // Tasks synced before block ids were turned on get one
+        let mut untracked = parsed.clone();
+        untracked.block_id = None;
+        assert!(
+            ts.md_to_tc(&mut untracked, "/vault/Inbox.md", Some("/vault"))
+                .unwrap()
+        );
+        assert_eq!(untracked.block_id, parsed.block_id);
+    }
+
+    #[test]
+    fn test_moved_task() {
+        let mut task = ObsidianTaskBuilder::new().description("Test").build();
+        let replica = create_mem_replica();
+        let mut ts = TaskWarriorSync::from_replica(replica, &UTC);
+        assert!(
+            ts.md_to_tc(&mut task, "/vault/Inbox.md", Some("/vault"))
+                .unwrap()
+        );
+
+        let uuid = task.uuid.unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("sourcefile"), Some("Inbox.md"));
+        assert!(ts.moves.is_empty());
+
+        // Syncing from the same note changes nothing
+        assert!(
+            !ts.md_to_tc(&mut task, "/vault/Inbox.md", Some("/vault"))
+                .unwrap()
+        );
+        assert!(ts.moves.is_empty());
+
+        ts.md_to_tc(&mut task, "/vault/Work/Acme.md", Some("/vault"))
+            .unwrap();
+        let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+        assert_eq!(tc_task.get_value("sourcefile"), Some("Work/Acme.md"));
+        let annotations: Vec<String> = tc_task
+            .get_annotations()
+            .map(|annotation| annotation.description)
+            .collect();
+        assert_eq!(
+            annotations,
+            ["obsidian://open?vault=vault&file=Work%2FAcme"]
+        );
+        assert_eq!(
+            ts.moves,
+            [TaskMove {
+                description: String::from("Test"),
+                from: String::from("Inbox.md"),
+                to: String::from("Work/Acme.md"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_timezone_round_trip_matrix() {
+        let dates = [
+            "2025-01-15",
+            "2025-03-09",
+            "2025-03-30",
+            "2025-09-07",
+            "2025-10-26",
+            "2025-11-02 01:30",
+            "2025-03-09 02:30",
+        ];
+        for tz in chrono_tz::TZ_VARIANTS {
+            let replica = create_mem_replica();
+            let mut ts = TaskWarriorSync::from_replica(replica, &tz);
+            for date in dates {
+                let mut o_task = ObsidianTaskBuilder::new()
+                    .tz(tz)
+                    .description("Test")
+                    .due_str(date)
+                    .scheduled_str(date)
+                    .build();
+                ts.md_to_tc(&mut o_task, "", None).unwrap();
+
+                let uuid = o_task.uuid.unwrap();
+                let tc_task = ts.replica.get_task(uuid).unwrap().unwrap();
+                assert!(o_task.compare_due(&tc_task), "{tz} {date}");
+                assert!(o_task.compare_schedule(&tc_task), "{tz} {date}");
+                assert!(ts.tc_to_md(&o_task).is_none(), "{tz} {date}");
+
+                let from_tc = ObsidianTask::from_tc(tc_task, &tz, &o_task.settings);
+                assert_eq!(
+                    from_tc.due.map(|due| due.date),
+                    o_task.due.map(|due| due.date)
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_timezone() {
         let mut o_task = ObsidianTaskBuilder::new()
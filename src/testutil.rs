@@ -1,6 +1,6 @@
 use taskchampion::{Operations, Replica, Status, StorageConfig, Task, Uuid};
 
-const MIDNIGHT: chrono::NaiveTime = chrono::NaiveTime::from_hms(0, 0, 0);
+use crate::datetime::TaskDate;
 
 pub struct TestContext<'b> {
     pub replica: &'b mut Replica,
@@ -15,10 +15,11 @@ pub struct TaskBuilder<'a, 'b> {
 macro_rules! tb_date_fn {
     ($tcData:tt) => {
         pub fn $tcData<T: AsRef<str>>(mut self, date: T) -> Self {
-            let dt = chrono::NaiveDate::parse_from_str(date.as_ref(), "%Y-%m-%d")
+            let dt = date
+                .as_ref()
+                .parse::<TaskDate>()
                 .unwrap()
-                .and_time(MIDNIGHT)
-                .and_utc();
+                .to_utc(&chrono_tz::UTC);
 
             let _ = self.task.set_value(
                 stringify!($tcData),
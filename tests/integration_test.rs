@@ -2,7 +2,8 @@
 #[cfg(test)]
 mod tests {
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::process::Output;
 
     use taskchampion::storage::AccessMode;
     use taskchampion::{self, Operations, Replica, Status};
@@ -10,6 +11,8 @@ mod tests {
     use test_bin::get_test_bin;
     use testdir;
 
+    use pretty_assertions::assert_eq;
+
     #[test]
     fn test_simple_md() {
         let mut sharptask = get_test_bin("sharptask");
@@ -125,4 +128,199 @@ mod tests {
         let mut handle = sharptask.spawn().unwrap();
         assert!(handle.wait().unwrap().success());
     }
+
+    // Copies simple.md into the test directory next to an empty task database,
+    // returning the database path
+    fn setup(path: &Path) -> PathBuf {
+        fs::copy("tests/simple.md", path.join("simple.md"));
+        let task_db = path.join("taskData");
+        let storage = taskchampion::StorageConfig::OnDisk {
+            taskdb_dir: task_db.clone(),
+            create_if_missing: true,
+            access_mode: AccessMode::ReadWrite,
+        }
+        .into_storage()
+        .unwrap();
+        drop(storage);
+        task_db
+    }
+
+    // Runs sharptask on the copy of simple.md, expecting it to succeed
+    fn run(path: &Path, task_db: &Path, args: &[&str]) -> Output {
+        let mut sharptask = get_test_bin("sharptask");
+        sharptask.args([
+            "--task-db",
+            task_db.to_str().unwrap(),
+            "--file",
+            path.join("simple.md").to_str().unwrap(),
+        ]);
+        sharptask.args(args);
+        let output = sharptask.output().unwrap();
+        assert!(output.status.success());
+        output
+    }
+
+    fn read_note(path: &Path) -> String {
+        fs::read_to_string(path.join("simple.md")).unwrap()
+    }
+
+    fn open_replica(task_db: &Path) -> Replica {
+        let storage = taskchampion::StorageConfig::OnDisk {
+            taskdb_dir: task_db.to_path_buf(),
+            create_if_missing: false,
+            access_mode: AccessMode::ReadOnly,
+        }
+        .into_storage()
+        .unwrap();
+        Replica::new(storage)
+    }
+
+    #[test]
+    fn test_migrate() {
+        let path = testdir::testdir!();
+        let task_db = setup(&path);
+
+        run(&path, &task_db, &["md-to-tc"]);
+        let linked = read_note(&path);
+        assert_eq!(linked.matches("[[uuid: ").count(), 2);
+
+        run(&path, &task_db, &["migrate", "--to", "comment"]);
+        let migrated = read_note(&path);
+        assert!(!migrated.contains("[[uuid: "));
+        assert_eq!(migrated.matches("%%tw:").count(), 2);
+
+        // The tasks are still recognised in their new format
+        run(&path, &task_db, &["md-to-tc"]);
+        assert_eq!(read_note(&path), migrated);
+        let mut replica = open_replica(&task_db);
+        assert_eq!(replica.all_task_uuids().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_report_time() {
+        let path = testdir::testdir!();
+        let task_db = setup(&path);
+
+        run(&path, &task_db, &["md-to-tc"]);
+        let before = read_note(&path);
+        let report = run(&path, &task_db, &["report", "time"]);
+        assert!(
+            String::from_utf8(report.stdout)
+                .unwrap()
+                .contains("Total: 0m")
+        );
+        // Reports never touch the notes
+        assert_eq!(read_note(&path), before);
+    }
+
+    #[test]
+    fn test_unlink() {
+        let path = testdir::testdir!();
+        let task_db = setup(&path);
+        let original = format!("{}- [ ] Own block id ^mine\n", read_note(&path));
+        fs::write(path.join("simple.md"), &original);
+        fs::write(path.join("config.toml"), "[markdown]\nblock_ids = true\n");
+        let config = path.join("config.toml");
+        let config = config.to_str().unwrap();
+
+        run(&path, &task_db, &["--config", config, "md-to-tc"]);
+        let linked = read_note(&path);
+        assert_eq!(linked.matches("[[uuid: ").count(), 3);
+        assert_eq!(linked.matches(" ^tw-").count(), 2);
+        assert!(linked.contains(" ^mine"));
+
+        // A dry run leaves everything alone
+        run(
+            &path,
+            &task_db,
+            &[
+                "--config",
+                config,
+                "unlink",
+                "--tasks",
+                "delete",
+                "--dry-run",
+            ],
+        );
+        assert_eq!(read_note(&path), linked);
+
+        run(
+            &path,
+            &task_db,
+            &["--config", config, "unlink", "--tasks", "delete"],
+        );
+        assert_eq!(read_note(&path), original);
+
+        let mut replica = open_replica(&task_db);
+        let tasks = replica.all_tasks().unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert!(
+            tasks
+                .values()
+                .all(|task| task.get_status() == Status::Deleted)
+        );
+    }
+
+    #[test]
+    fn test_sync_server() {
+        let path = testdir::testdir!();
+        let task_db = setup(&path);
+        let server_dir = path.join("server");
+        fs::write(
+            path.join("config.toml"),
+            format!("[sync]\nserver_dir = {:?}\n", server_dir.to_str().unwrap()),
+        );
+
+        let output = run(
+            &path,
+            &task_db,
+            &[
+                "--config",
+                path.join("config.toml").to_str().unwrap(),
+                "md-to-tc",
+            ],
+        );
+        assert!(
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .contains("Synced with the server")
+        );
+
+        // Another replica gets the new tasks from the server
+        let storage = taskchampion::StorageConfig::InMemory
+            .into_storage()
+            .unwrap();
+        let mut replica = Replica::new(storage);
+        let mut server = taskchampion::ServerConfig::Local { server_dir }
+            .into_server()
+            .unwrap();
+        replica.sync(&mut server, false).unwrap();
+        assert_eq!(replica.all_task_uuids().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_taskwarrior2_backend() {
+        let path = testdir::testdir!();
+        fs::copy("tests/simple.md", path.join("simple.md"));
+        let task_db = path.join("task");
+        fs::create_dir(&task_db);
+        let existing = "[description:\"Existing task\" entry:\"1749513600\" status:\"pending\" uuid:\"4e6ac96b-4d7c-4cbb-a4a4-63e1ed4fd4b3\"]\n";
+        fs::write(task_db.join("pending.data"), existing);
+
+        run(&path, &task_db, &["--backend", "taskwarrior2", "md-to-tc"]);
+
+        // Existing tasks keep their line and id, new ones are added after them
+        let pending = fs::read_to_string(task_db.join("pending.data")).unwrap();
+        let lines: Vec<&str> = pending.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(format!("{}\n", lines[0]), existing);
+        assert!(lines[1].contains("description:\"Unsynced task\""));
+        let completed = fs::read_to_string(task_db.join("completed.data")).unwrap();
+        assert!(completed.contains("description:\"Completed task\""));
+        assert!(completed.contains("status:\"completed\""));
+        let undo = fs::read_to_string(task_db.join("undo.data")).unwrap();
+        assert_eq!(undo.matches("---").count(), 2);
+
+        assert_eq!(read_note(&path).matches("[[uuid: ").count(), 2);
+    }
 }